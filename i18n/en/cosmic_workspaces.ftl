@@ -1,2 +1,11 @@
 workspace = Workspace { $number }
 new-workspace = New Workspace
+minimize = Minimize
+unminimize = Unminimize
+maximize = Maximize
+unmaximize = Unmaximize
+fullscreen = Fullscreen
+unfullscreen = Exit Fullscreen
+sticky = Show on All Workspaces
+move-to-workspace = Move to { $name }
+move-to-output = Move to Display { $name }
@@ -0,0 +1,117 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Timing for the overview open/close transition, and for previews moving to new
+//! positions.
+
+use cosmic::iced::{Point, Rectangle, Size};
+use std::time::{Duration, Instant};
+
+pub const DURATION: Duration = Duration::from_millis(250);
+
+/// Cubic ease-out, for `t` from 0 to 1
+pub fn ease(t: f32) -> f32 {
+    1. - (1. - t.clamp(0., 1.)).powi(3)
+}
+
+/// Fraction of `DURATION` elapsed since `start`, from 0 to 1
+pub fn linear_progress(start: Instant, now: Instant) -> f32 {
+    (now.saturating_duration_since(start).as_secs_f32() / DURATION.as_secs_f32()).min(1.)
+}
+
+pub fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+pub fn lerp_rect(from: Rectangle, to: Rectangle, t: f32) -> Rectangle {
+    Rectangle::new(
+        Point::new(lerp(from.x, to.x, t), lerp(from.y, to.y, t)),
+        Size::new(
+            lerp(from.width, to.width, t),
+            lerp(from.height, to.height, t),
+        ),
+    )
+}
+
+/// Inverse of `ease`
+fn ease_inverse(t: f32) -> f32 {
+    1. - (1. - t.clamp(0., 1.)).cbrt()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Driver {
+    /// Animated over `DURATION`, starting at this time
+    Time(Instant),
+    /// Following a gesture, that started with the overview shown by `from`
+    Gesture { from: f32, progress: f32 },
+}
+
+/// Overview being shown or hidden
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    driver: Driver,
+    pub opening: bool,
+}
+
+impl Transition {
+    pub fn opening(now: Instant) -> Self {
+        Self {
+            driver: Driver::Time(now),
+            opening: true,
+        }
+    }
+
+    /// Animate towards shown or hidden, starting with the overview shown by `progress`
+    pub fn at_progress(progress: f32, opening: bool, now: Instant) -> Self {
+        let t = ease_inverse(if opening { progress } else { 1. - progress });
+        Self {
+            driver: Driver::Time(now.checked_sub(DURATION.mul_f32(t)).unwrap_or(now)),
+            opening,
+        }
+    }
+
+    /// Go in the other direction, starting from the current progress
+    pub fn reverse(self, now: Instant) -> Self {
+        Self::at_progress(self.progress(now), !self.opening, now)
+    }
+
+    /// Follow a gesture, instead of animating, starting with the overview shown by
+    /// `from`
+    pub fn gesture(from: f32, opening: bool) -> Self {
+        Self {
+            driver: Driver::Gesture { from, progress: 0. },
+            opening,
+        }
+    }
+
+    pub fn is_gesture(&self) -> bool {
+        matches!(self.driver, Driver::Gesture { .. })
+    }
+
+    /// Set how far the gesture has progressed, from 0 to 1
+    pub fn update_gesture(&mut self, new_progress: f32) {
+        if let Driver::Gesture { progress, .. } = &mut self.driver {
+            *progress = new_progress.clamp(0., 1.);
+        }
+    }
+
+    /// How far the overview is shown, from 0 (hidden) to 1 (fully shown)
+    pub fn progress(&self, now: Instant) -> f32 {
+        match self.driver {
+            Driver::Time(start) => {
+                let t = ease(linear_progress(start, now));
+                if self.opening { t } else { 1. - t }
+            }
+            Driver::Gesture { from, progress } => {
+                lerp(from, if self.opening { 1. } else { 0. }, progress)
+            }
+        }
+    }
+
+    pub fn is_finished(&self, now: Instant) -> bool {
+        match self.driver {
+            Driver::Time(start) => now.saturating_duration_since(start) >= DURATION,
+            Driver::Gesture { .. } => false,
+        }
+    }
+}
@@ -0,0 +1,723 @@
+// Copyright 2024 System76 <info@system76.com>
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::cctk::cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1;
+use cosmic::cctk::cosmic_protocols::toplevel_management::v1::client::zcosmic_toplevel_manager_v1;
+use cosmic::cctk::cosmic_protocols::workspace::v2::client::zcosmic_workspace_handle_v2;
+use cosmic::cctk::wayland_client::Connection;
+use cosmic::cctk::wayland_client::protocol::{wl_output, wl_shm};
+use cosmic::cctk::wayland_protocols::ext::workspace::v1::client::{
+    ext_workspace_group_handle_v1, ext_workspace_handle_v1,
+};
+use cosmic::iced::futures::executor::block_on;
+use cosmic::iced::futures::{FutureExt, SinkExt};
+use cosmic::iced::{self};
+use cosmic::iced_winit::platform_specific::wayland::subsurface_widget::{Shmbuf, SubsurfaceBuffer};
+
+use calloop::timer::{TimeoutAction, Timer};
+use futures_channel::mpsc;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use std::{fs, thread};
+
+use super::{
+    CaptureImage, Cmd, Event, ToplevelGeometry, ToplevelHandle, ToplevelInfo, Workspace,
+    WorkspaceGroup, WorkspaceHandle,
+};
+use crate::utils;
+
+mod scenario;
+use scenario::{Scenario, ScenarioEvent, ToplevelScenario, WorkspaceScenario};
+
+#[derive(Eq, PartialEq, Clone, Debug, Hash)]
+pub struct MockObjectId(u32);
+
+impl MockObjectId {
+    pub fn new() -> Self {
+        static NEXT_MOCK_ID: AtomicU32 = AtomicU32::new(0);
+        Self(NEXT_MOCK_ID.fetch_add(1, Ordering::SeqCst))
+    }
+
+    pub fn protocol_id(&self) -> u32 {
+        self.0
+    }
+}
+
+pub(super) fn create_solid_capture_image(r: u8, g: u8, b: u8) -> CaptureImage {
+    let file = fs::File::from(utils::create_memfile().unwrap());
+    let mut file = io::BufWriter::new(file);
+
+    for _ in 0..512 * 512 {
+        file.write_all(&[b, g, r, 255]).unwrap();
+    }
+
+    CaptureImage {
+        width: 512,
+        height: 512,
+        bytes_per_pixel: 4,
+        wl_buffer: SubsurfaceBuffer::new(Arc::new(
+            Shmbuf {
+                fd: file.into_inner().unwrap().into(),
+                offset: 0,
+                width: 512,
+                height: 512,
+                stride: 512 * 4,
+                format: wl_shm::Format::Argb8888,
+            }
+            .into(),
+        ))
+        .0,
+        transform: wl_output::Transform::Normal,
+        #[cfg(feature = "no-subsurfaces")]
+        image: cosmic::widget::image::Handle::from_rgba(512, 512, [r, g, b, 255].repeat(512 * 512)),
+    }
+}
+
+/// Move toplevel to other outputs, keeping its position
+fn set_toplevel_outputs(info: &mut ToplevelInfo, outputs: HashSet<wl_output::WlOutput>) {
+    let geometry = info.geometry.values().next().copied().unwrap_or_default();
+    info.geometry = outputs.iter().map(|o| (o.clone(), geometry)).collect();
+    info.output = outputs;
+}
+
+pub fn subscription(conn: Connection) -> iced::Subscription<Event> {
+    iced::Subscription::run_with_id("wayland-mock-sub", async { start(conn) }.flatten_stream())
+}
+
+struct MockToplevel {
+    handle: ToplevelHandle,
+    info: ToplevelInfo,
+    /// Identifier from scenario, if any
+    id: Option<String>,
+}
+
+struct AppData {
+    sender: mpsc::Sender<Event>,
+    scenario: Scenario,
+    outputs: Vec<wl_output::WlOutput>,
+    workspaces: Vec<(HashSet<wl_output::WlOutput>, Workspace)>,
+    toplevels: Vec<MockToplevel>,
+}
+
+impl AppData {
+    fn send_event(&mut self, event: Event) {
+        let _ = block_on(self.sender.send(event));
+    }
+
+    fn add_output(&mut self, output: &wl_output::WlOutput) {
+        let output_idx = self.outputs.len();
+        self.outputs.push(output.clone());
+
+        let mut new_workspaces = Vec::new();
+        let mut new_toplevels = Vec::new();
+        for (i, workspace_scenario) in self
+            .scenario
+            .output(output_idx)
+            .into_iter()
+            .flat_map(|o| &o.workspaces)
+            .enumerate()
+        {
+            let workspace_handle = WorkspaceHandle::Mock(MockObjectId::new());
+            let workspace = Workspace {
+                handle: workspace_handle.clone(),
+                name: workspace_scenario.name.clone(),
+                coordinates: workspace_scenario
+                    .coordinates
+                    .clone()
+                    .unwrap_or_else(|| vec![i as u32]),
+                state: workspace_scenario.state(),
+                capabilities: workspace_scenario.capabilities(),
+                cosmic_capabilities: workspace_scenario.cosmic_capabilities(),
+                cosmic_state: workspace_scenario.cosmic_state(),
+            };
+            for toplevel in &workspace_scenario.toplevels {
+                new_toplevels.push((toplevel.clone(), workspace_handle.clone()));
+            }
+            self.workspaces
+                .push((HashSet::from([output.clone()]), workspace));
+            new_workspaces.push(workspace_handle);
+        }
+        // Scenario toplevels are only created once the overview is shown on the
+        // output, so they mustn't be mistaken for windows spawning
+        for (toplevel, workspace_handle) in new_toplevels {
+            self.add_toplevel(&toplevel, output, workspace_handle, true);
+        }
+        self.send_workspaces();
+        for workspace_handle in new_workspaces {
+            self.send_event(Event::WorkspaceCapture(
+                workspace_handle,
+                create_solid_capture_image(0, 255, 0),
+            ));
+        }
+    }
+
+    fn add_toplevel(
+        &mut self,
+        toplevel: &ToplevelScenario,
+        output: &wl_output::WlOutput,
+        workspace_handle: WorkspaceHandle,
+        initial: bool,
+    ) {
+        let handle = ToplevelHandle::Mock(MockObjectId::new());
+        // Cascade toplevels that don't specify a position
+        let n = self
+            .toplevels
+            .iter()
+            .filter(|t| t.info.workspace.contains(&workspace_handle))
+            .count() as i32
+            % 4;
+        let (x, y, width, height) =
+            toplevel
+                .geometry
+                .unwrap_or((80 + 240 * n, 60 + 120 * n, 960, 640));
+        let geometry = ToplevelGeometry {
+            x,
+            y,
+            width,
+            height,
+        };
+        let info = ToplevelInfo {
+            title: toplevel.title.clone(),
+            app_id: toplevel.app_id.clone(),
+            state: toplevel.states.iter().map(|s| (*s).into()).collect(),
+            output: HashSet::from([output.clone()]),
+            workspace: HashSet::from([workspace_handle]),
+            geometry: HashMap::from([(output.clone(), geometry)]),
+        };
+        self.send_event(Event::NewToplevel(handle.clone(), info.clone(), initial));
+        let (r, g, b) = toplevel.color;
+        self.send_event(Event::ToplevelCapture(
+            handle.clone(),
+            create_solid_capture_image(r, g, b),
+        ));
+        self.toplevels.push(MockToplevel {
+            handle,
+            info,
+            id: toplevel.id.clone(),
+        });
+    }
+
+    /// Find workspace by index of output, and index of workspace on that output
+    fn workspace_at(
+        &self,
+        output_idx: usize,
+        workspace_idx: usize,
+    ) -> Option<(wl_output::WlOutput, WorkspaceHandle)> {
+        let output = self.outputs.get(output_idx)?;
+        let (_, workspace) = self
+            .workspaces
+            .iter()
+            .filter(|(outputs, _)| outputs.contains(output))
+            .nth(workspace_idx)?;
+        Some((output.clone(), workspace.handle.clone()))
+    }
+
+    fn toplevel_idx(&self, id: &str) -> Option<usize> {
+        self.toplevels
+            .iter()
+            .position(|t| t.id.as_deref() == Some(id))
+    }
+
+    fn handle_scenario_event(&mut self, event: ScenarioEvent) {
+        match event {
+            ScenarioEvent::Open {
+                output,
+                workspace,
+                toplevel,
+            } => {
+                let Some((output, workspace)) = self.workspace_at(output, workspace) else {
+                    log::warn!("Mock scenario: no workspace {workspace} on output {output}");
+                    return;
+                };
+                self.add_toplevel(&toplevel, &output, workspace, false);
+            }
+            ScenarioEvent::Close { id } => {
+                let Some(idx) = self.toplevel_idx(&id) else {
+                    log::warn!("Mock scenario: no toplevel with id '{id}'");
+                    return;
+                };
+                let toplevel = self.toplevels.remove(idx);
+                self.send_event(Event::CloseToplevel(toplevel.handle));
+            }
+            ScenarioEvent::Retitle { id, title } => {
+                let Some(idx) = self.toplevel_idx(&id) else {
+                    log::warn!("Mock scenario: no toplevel with id '{id}'");
+                    return;
+                };
+                self.toplevels[idx].info.title = title;
+                let toplevel = &self.toplevels[idx];
+                let event = Event::UpdateToplevel(toplevel.handle.clone(), toplevel.info.clone());
+                self.send_event(event);
+            }
+            ScenarioEvent::Move {
+                id,
+                output,
+                workspace,
+            } => {
+                let Some(idx) = self.toplevel_idx(&id) else {
+                    log::warn!("Mock scenario: no toplevel with id '{id}'");
+                    return;
+                };
+                let Some((output, workspace)) = self.workspace_at(output, workspace) else {
+                    log::warn!("Mock scenario: no workspace {workspace} on output {output}");
+                    return;
+                };
+                let toplevel = &mut self.toplevels[idx];
+                set_toplevel_outputs(&mut toplevel.info, HashSet::from([output]));
+                toplevel.info.workspace = HashSet::from([workspace]);
+                let event = Event::UpdateToplevel(toplevel.handle.clone(), toplevel.info.clone());
+                self.send_event(event);
+            }
+        }
+    }
+
+    fn handle_cmd(&mut self, cmd: Cmd) {
+        match cmd {
+            Cmd::CaptureFilter(filter) => {
+                for output in &filter.workspaces_on_outputs {
+                    if !self.outputs.contains(output) {
+                        self.add_output(output);
+                    }
+                }
+            }
+            Cmd::ActivateToplevel(toplevel_handle) => self.activate_toplevel(&toplevel_handle),
+            Cmd::CloseToplevel(toplevel_handle) => {
+                if let Some(idx) = self
+                    .toplevels
+                    .iter()
+                    .position(|t| t.handle == toplevel_handle)
+                {
+                    self.toplevels.remove(idx);
+                    self.send_event(Event::CloseToplevel(toplevel_handle));
+                }
+            }
+            Cmd::SetToplevelMinimized(toplevel_handle, value) => {
+                self.set_toplevel_state(
+                    &toplevel_handle,
+                    zcosmic_toplevel_handle_v1::State::Minimized,
+                    value,
+                );
+            }
+            Cmd::SetToplevelMaximized(toplevel_handle, value) => {
+                self.set_toplevel_state(
+                    &toplevel_handle,
+                    zcosmic_toplevel_handle_v1::State::Maximized,
+                    value,
+                );
+            }
+            Cmd::SetToplevelFullscreen(toplevel_handle, value) => {
+                self.set_toplevel_state(
+                    &toplevel_handle,
+                    zcosmic_toplevel_handle_v1::State::Fullscreen,
+                    value,
+                );
+            }
+            Cmd::SetToplevelSticky(toplevel_handle, value) => {
+                self.set_toplevel_state(
+                    &toplevel_handle,
+                    zcosmic_toplevel_handle_v1::State::Sticky,
+                    value,
+                );
+            }
+            Cmd::MoveToplevelToWorkspace(toplevel_handle, workspace_handle, output) => {
+                if let Some(toplevel) = self
+                    .toplevels
+                    .iter_mut()
+                    .find(|t| t.handle == toplevel_handle)
+                {
+                    set_toplevel_outputs(&mut toplevel.info, HashSet::from([output]));
+                    toplevel.info.workspace = HashSet::from([workspace_handle]);
+                    let event =
+                        Event::UpdateToplevel(toplevel.handle.clone(), toplevel.info.clone());
+                    self.send_event(event);
+                }
+            }
+            Cmd::MoveToplevelToNewWorkspace(toplevel_handle, output, name) => {
+                if let Some(workspace_handle) = self.create_workspace(output.clone(), name) {
+                    self.handle_cmd(Cmd::MoveToplevelToWorkspace(
+                        toplevel_handle,
+                        workspace_handle,
+                        output,
+                    ));
+                }
+            }
+            Cmd::ActivateWorkspace(workspace_handle) => {
+                if self.activate_workspace(&workspace_handle) {
+                    self.send_workspaces();
+                }
+            }
+            Cmd::MoveWorkspaceBefore(workspace_handle, other_workspace_handle) => {
+                self.move_workspace(&workspace_handle, &other_workspace_handle, false);
+            }
+            Cmd::MoveWorkspaceAfter(workspace_handle, other_workspace_handle) => {
+                self.move_workspace(&workspace_handle, &other_workspace_handle, true);
+            }
+            Cmd::MoveWorkspaceToOutput(workspace_handle, output, other_workspace_handle) => {
+                self.move_workspace_to_output(
+                    &workspace_handle,
+                    output,
+                    other_workspace_handle.as_ref(),
+                );
+            }
+            Cmd::SetWorkspacePinned(workspace_handle, pinned) => {
+                if let Some((_, workspace)) = self
+                    .workspaces
+                    .iter_mut()
+                    .find(|(_, w)| w.handle == workspace_handle)
+                {
+                    workspace
+                        .cosmic_state
+                        .set(zcosmic_workspace_handle_v2::State::Pinned, pinned);
+                    self.send_workspaces();
+                }
+            }
+            Cmd::RenameWorkspace(workspace_handle, name) => {
+                if let Some((_, workspace)) = self
+                    .workspaces
+                    .iter_mut()
+                    .find(|(_, w)| w.handle == workspace_handle)
+                {
+                    workspace.name = name;
+                    self.send_workspaces();
+                }
+            }
+            Cmd::CreateWorkspace(output, name) => {
+                self.create_workspace(output, name);
+            }
+            Cmd::RemoveWorkspace(workspace_handle) => self.remove_workspace(&workspace_handle),
+        }
+    }
+
+    /// Make workspace the only active one on its outputs. Returns `false` if
+    /// workspace doesn't exist.
+    fn activate_workspace(&mut self, handle: &WorkspaceHandle) -> bool {
+        let Some((outputs, _)) = self.workspaces.iter().find(|(_, w)| &w.handle == handle) else {
+            return false;
+        };
+        let outputs = outputs.clone();
+        for (workspace_outputs, workspace) in &mut self.workspaces {
+            if !workspace_outputs.is_disjoint(&outputs) {
+                workspace.state.set(
+                    ext_workspace_handle_v1::State::Active,
+                    &workspace.handle == handle,
+                );
+            }
+        }
+        true
+    }
+
+    /// Activate toplevel, deactivating all others, and switch to its workspace
+    fn activate_toplevel(&mut self, handle: &ToplevelHandle) {
+        let Some(workspaces) = self
+            .toplevels
+            .iter()
+            .find(|t| &t.handle == handle)
+            .map(|t| t.info.workspace.clone())
+        else {
+            return;
+        };
+        let mut events = Vec::new();
+        for toplevel in &mut self.toplevels {
+            let activated = &toplevel.handle == handle;
+            let changed = if activated {
+                toplevel
+                    .info
+                    .state
+                    .insert(zcosmic_toplevel_handle_v1::State::Activated)
+            } else {
+                toplevel
+                    .info
+                    .state
+                    .remove(&zcosmic_toplevel_handle_v1::State::Activated)
+            };
+            if changed {
+                events.push(Event::UpdateToplevel(
+                    toplevel.handle.clone(),
+                    toplevel.info.clone(),
+                ));
+            }
+        }
+        for workspace in &workspaces {
+            self.activate_workspace(workspace);
+        }
+        for event in events {
+            self.send_event(event);
+        }
+        self.send_workspaces();
+    }
+
+    /// Set or unset `state` of toplevel, sending an update if it changed
+    fn set_toplevel_state(
+        &mut self,
+        handle: &ToplevelHandle,
+        state: zcosmic_toplevel_handle_v1::State,
+        value: bool,
+    ) {
+        if let Some(toplevel) = self.toplevels.iter_mut().find(|t| &t.handle == handle) {
+            let changed = if value {
+                toplevel.info.state.insert(state)
+            } else {
+                toplevel.info.state.remove(&state)
+            };
+            if changed {
+                let event = Event::UpdateToplevel(toplevel.handle.clone(), toplevel.info.clone());
+                self.send_event(event);
+            }
+        }
+    }
+
+    /// Move workspace before or after `other`, onto the outputs of `other`, and
+    /// renumber coordinates of all workspaces.
+    fn move_workspace(&mut self, handle: &WorkspaceHandle, other: &WorkspaceHandle, after: bool) {
+        if handle == other {
+            return;
+        }
+        let Some(idx) = self
+            .workspaces
+            .iter()
+            .position(|(_, w)| &w.handle == handle)
+        else {
+            return;
+        };
+        if !self.workspaces.iter().any(|(_, w)| &w.handle == other) {
+            return;
+        }
+        let (_, workspace) = self.workspaces.remove(idx);
+        let other_idx = self
+            .workspaces
+            .iter()
+            .position(|(_, w)| &w.handle == other)
+            .unwrap();
+        let outputs = self.workspaces[other_idx].0.clone();
+        let insert_idx = if after { other_idx + 1 } else { other_idx };
+        self.workspaces
+            .insert(insert_idx, (outputs.clone(), workspace));
+        self.workspace_moved(handle, outputs);
+    }
+
+    /// Assign workspace to the outputs of another group, before `other` if given,
+    /// or at the end of the group
+    fn move_workspace_to_output(
+        &mut self,
+        handle: &WorkspaceHandle,
+        output: wl_output::WlOutput,
+        other: Option<&WorkspaceHandle>,
+    ) {
+        let Some(idx) = self
+            .workspaces
+            .iter()
+            .position(|(_, w)| &w.handle == handle)
+        else {
+            return;
+        };
+        if !self.workspaces[idx]
+            .1
+            .capabilities
+            .contains(ext_workspace_handle_v1::WorkspaceCapabilities::Assign)
+        {
+            return;
+        }
+        if let Some(other) = other {
+            self.move_workspace(handle, other, false);
+            return;
+        }
+        let (_, workspace) = self.workspaces.remove(idx);
+        let outputs = HashSet::from([output]);
+        self.workspaces.push((outputs.clone(), workspace));
+        self.workspace_moved(handle, outputs);
+    }
+
+    fn workspace_moved(&mut self, handle: &WorkspaceHandle, outputs: HashSet<wl_output::WlOutput>) {
+        // Toplevels follow workspace if it moved to another output
+        let mut events = Vec::new();
+        for toplevel in &mut self.toplevels {
+            if toplevel.info.workspace.contains(handle) && toplevel.info.output != outputs {
+                set_toplevel_outputs(&mut toplevel.info, outputs.clone());
+                events.push(Event::UpdateToplevel(
+                    toplevel.handle.clone(),
+                    toplevel.info.clone(),
+                ));
+            }
+        }
+
+        self.renumber_workspaces();
+        for event in events {
+            self.send_event(event);
+        }
+        self.send_workspaces();
+    }
+
+    /// Number workspaces by index within the group of workspaces on the same outputs
+    fn renumber_workspaces(&mut self) {
+        for i in 0..self.workspaces.len() {
+            let n = self.workspaces[..i]
+                .iter()
+                .filter(|(outputs, _)| outputs == &self.workspaces[i].0)
+                .count();
+            self.workspaces[i].1.coordinates = vec![n as u32];
+        }
+    }
+
+    fn create_workspace(
+        &mut self,
+        output: wl_output::WlOutput,
+        name: String,
+    ) -> Option<WorkspaceHandle> {
+        let output_idx = self.outputs.iter().position(|o| *o == output)?;
+        if !self
+            .scenario
+            .output(output_idx)
+            .is_some_and(|o| o.can_create_workspace)
+        {
+            return None;
+        }
+        let handle = WorkspaceHandle::Mock(MockObjectId::new());
+        let workspace_scenario = WorkspaceScenario::default();
+        let workspace = Workspace {
+            handle: handle.clone(),
+            name,
+            coordinates: Vec::new(),
+            state: workspace_scenario.state(),
+            capabilities: workspace_scenario.capabilities(),
+            cosmic_capabilities: workspace_scenario.cosmic_capabilities(),
+            cosmic_state: workspace_scenario.cosmic_state(),
+        };
+        self.workspaces.push((HashSet::from([output]), workspace));
+        self.renumber_workspaces();
+        self.send_workspaces();
+        self.send_event(Event::WorkspaceCapture(
+            handle.clone(),
+            create_solid_capture_image(0, 255, 0),
+        ));
+        Some(handle)
+    }
+
+    /// Remove workspace, moving its toplevels to the first workspace on the same
+    /// outputs
+    fn remove_workspace(&mut self, handle: &WorkspaceHandle) {
+        let Some(idx) = self
+            .workspaces
+            .iter()
+            .position(|(_, w)| &w.handle == handle)
+        else {
+            return;
+        };
+        if !self.workspaces[idx]
+            .1
+            .capabilities
+            .contains(ext_workspace_handle_v1::WorkspaceCapabilities::Remove)
+        {
+            return;
+        }
+        let (outputs, workspace) = self.workspaces.remove(idx);
+        let fallback = self
+            .workspaces
+            .iter()
+            .find(|(o, _)| *o == outputs)
+            .map(|(_, w)| w.handle.clone());
+        if workspace
+            .state
+            .contains(ext_workspace_handle_v1::State::Active)
+            && let Some(fallback) = &fallback
+        {
+            self.activate_workspace(fallback);
+        }
+        let mut events = Vec::new();
+        for toplevel in &mut self.toplevels {
+            if toplevel.info.workspace.remove(handle) {
+                toplevel.info.workspace.extend(fallback.clone());
+                events.push(Event::UpdateToplevel(
+                    toplevel.handle.clone(),
+                    toplevel.info.clone(),
+                ));
+            }
+        }
+        self.renumber_workspaces();
+        for event in events {
+            self.send_event(event);
+        }
+        self.send_workspaces();
+    }
+
+    fn send_workspaces(&mut self) {
+        let groups = self
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(i, output)| {
+                let mut capabilities = ext_workspace_group_handle_v1::GroupCapabilities::empty();
+                if self
+                    .scenario
+                    .output(i)
+                    .is_some_and(|o| o.can_create_workspace)
+                {
+                    capabilities |=
+                        ext_workspace_group_handle_v1::GroupCapabilities::CreateWorkspace;
+                }
+                WorkspaceGroup {
+                    outputs: HashSet::from([output.clone()]),
+                    capabilities,
+                }
+            })
+            .collect();
+        self.send_event(Event::WorkspaceGroups(groups));
+        self.send_event(Event::Workspaces(self.workspaces.clone()));
+    }
+}
+
+fn start(_conn: Connection) -> mpsc::Receiver<Event> {
+    let (sender, receiver) = mpsc::channel(20);
+    thread::spawn(move || {
+        let mut event_loop = calloop::EventLoop::try_new().unwrap();
+        let (cmd_sender, cmd_channel) = calloop::channel::channel();
+        event_loop
+            .handle()
+            .insert_source(cmd_channel, |cmd, (), app_data: &mut AppData| match cmd {
+                calloop::channel::Event::Msg(cmd) => app_data.handle_cmd(cmd),
+                calloop::channel::Event::Closed => {}
+            })
+            .unwrap();
+
+        let scenario = Scenario::from_env();
+        for timed_event in &scenario.events {
+            let event = timed_event.event.clone();
+            event_loop
+                .handle()
+                .insert_source(
+                    Timer::from_duration(Duration::from_millis(timed_event.after_ms)),
+                    move |_, (), app_data: &mut AppData| {
+                        app_data.handle_scenario_event(event.clone());
+                        TimeoutAction::Drop
+                    },
+                )
+                .unwrap();
+        }
+
+        let mut app_data = AppData {
+            sender,
+            scenario,
+            outputs: Vec::new(),
+            workspaces: Vec::new(),
+            toplevels: Vec::new(),
+        };
+        app_data.send_event(Event::ToplevelCapabilities(vec![
+            zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1::Close,
+            zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1::Activate,
+            zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1::Maximize,
+            zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1::Minimize,
+            zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1::Fullscreen,
+            zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1::MoveToWorkspace,
+            zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1::Sticky,
+        ]));
+        app_data.send_event(Event::CmdSender(cmd_sender));
+        loop {
+            event_loop.dispatch(None, &mut app_data).unwrap();
+        }
+    });
+    receiver
+}
+
+// TODO WorkspaceCapture, ToplevelCapture, NewToplevel, Workspaces
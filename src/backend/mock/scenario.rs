@@ -0,0 +1,290 @@
+//! Scenario files for the mock backend, describing workspaces and toplevels on
+//! each output, and events to trigger after a delay.
+//!
+//! Used with `--backend mock`. Loaded from the path in
+//! `COSMIC_WORKSPACES_MOCK_SCENARIO`, in RON format. For example:
+//!
+//! ```ron
+//! (
+//!     outputs: [
+//!         (workspaces: [
+//!             (
+//!                 name: "1",
+//!                 active: true,
+//!                 capabilities: [Activate, Assign, Pin, Move],
+//!                 toplevels: [
+//!                     (id: Some("term"), title: "Terminal", app_id: "com.system76.CosmicTerm", states: [Activated]),
+//!                     (title: "Files", app_id: "com.system76.CosmicFiles", color: (0, 0, 255), geometry: Some((960, 0, 960, 1080))),
+//!                 ],
+//!             ),
+//!             (name: "2", pinned: true),
+//!         ]),
+//!     ],
+//!     events: [
+//!         (after_ms: 2000, event: Retitle(id: "term", title: "vim")),
+//!         (after_ms: 4000, event: Move(id: "term", output: 0, workspace: 1)),
+//!         (after_ms: 6000, event: Close(id: "term")),
+//!     ],
+//! )
+//! ```
+
+use cosmic::cctk::cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1;
+use cosmic::cctk::cosmic_protocols::workspace::v2::client::zcosmic_workspace_handle_v2;
+use cosmic::cctk::wayland_protocols::ext::workspace::v1::client::ext_workspace_handle_v1;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    /// Workspaces and toplevels for each output, in the order outputs are added.
+    /// If there are more outputs than entries, the last entry is used again.
+    pub outputs: Vec<OutputScenario>,
+    pub events: Vec<TimedEvent>,
+}
+
+impl Default for Scenario {
+    // Five workspaces with four toplevels each, on every output
+    fn default() -> Self {
+        Self {
+            outputs: vec![OutputScenario {
+                workspaces: (0..=4)
+                    .map(|i| WorkspaceScenario {
+                        name: format!("Workspace {i}"),
+                        active: i == 0,
+                        toplevels: (0..=3)
+                            .map(|j| ToplevelScenario {
+                                title: format!("App {j}"),
+                                states: if i == 0 {
+                                    vec![ToplevelState::Activated]
+                                } else {
+                                    Vec::new()
+                                },
+                                ..Default::default()
+                            })
+                            .collect(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+            events: Vec::new(),
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(ron::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Load from `COSMIC_WORKSPACES_MOCK_SCENARIO`, or use the default scenario
+    pub fn from_env() -> Self {
+        let Some(path) = std::env::var_os("COSMIC_WORKSPACES_MOCK_SCENARIO") else {
+            return Self::default();
+        };
+        match Self::load(Path::new(&path)) {
+            Ok(scenario) => scenario,
+            Err(err) => {
+                log::error!(
+                    "Failed to load mock scenario '{}': {}",
+                    Path::new(&path).display(),
+                    err
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn output(&self, idx: usize) -> Option<&OutputScenario> {
+        self.outputs.get(idx).or(self.outputs.last())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct OutputScenario {
+    pub workspaces: Vec<WorkspaceScenario>,
+    /// Whether the workspace group allows creating workspaces
+    pub can_create_workspace: bool,
+}
+
+impl Default for OutputScenario {
+    fn default() -> Self {
+        Self {
+            workspaces: Vec::new(),
+            can_create_workspace: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceScenario {
+    pub name: String,
+    /// Defaults to index of workspace on output
+    pub coordinates: Option<Vec<u32>>,
+    pub active: bool,
+    pub urgent: bool,
+    pub hidden: bool,
+    pub pinned: bool,
+    pub capabilities: Vec<WorkspaceCapability>,
+    pub toplevels: Vec<ToplevelScenario>,
+}
+
+impl Default for WorkspaceScenario {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            coordinates: None,
+            active: false,
+            urgent: false,
+            hidden: false,
+            pinned: false,
+            capabilities: vec![WorkspaceCapability::Activate, WorkspaceCapability::Remove],
+            toplevels: Vec::new(),
+        }
+    }
+}
+
+impl WorkspaceScenario {
+    pub fn state(&self) -> ext_workspace_handle_v1::State {
+        let mut state = ext_workspace_handle_v1::State::empty();
+        state.set(ext_workspace_handle_v1::State::Active, self.active);
+        state.set(ext_workspace_handle_v1::State::Urgent, self.urgent);
+        state.set(ext_workspace_handle_v1::State::Hidden, self.hidden);
+        state
+    }
+
+    pub fn cosmic_state(&self) -> zcosmic_workspace_handle_v2::State {
+        let mut state = zcosmic_workspace_handle_v2::State::empty();
+        state.set(zcosmic_workspace_handle_v2::State::Pinned, self.pinned);
+        state
+    }
+
+    pub fn capabilities(&self) -> ext_workspace_handle_v1::WorkspaceCapabilities {
+        self.capabilities.iter().filter_map(|c| c.ext()).fold(
+            ext_workspace_handle_v1::WorkspaceCapabilities::empty(),
+            |a, b| a | b,
+        )
+    }
+
+    pub fn cosmic_capabilities(&self) -> zcosmic_workspace_handle_v2::WorkspaceCapabilities {
+        self.capabilities.iter().filter_map(|c| c.cosmic()).fold(
+            zcosmic_workspace_handle_v2::WorkspaceCapabilities::empty(),
+            |a, b| a | b,
+        )
+    }
+}
+
+/// Capabilities from both `ext_workspace_handle_v1` and `zcosmic_workspace_handle_v2`
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum WorkspaceCapability {
+    Activate,
+    Deactivate,
+    Remove,
+    Assign,
+    Rename,
+    Pin,
+    Move,
+}
+
+impl WorkspaceCapability {
+    fn ext(self) -> Option<ext_workspace_handle_v1::WorkspaceCapabilities> {
+        match self {
+            Self::Activate => Some(ext_workspace_handle_v1::WorkspaceCapabilities::Activate),
+            Self::Deactivate => Some(ext_workspace_handle_v1::WorkspaceCapabilities::Deactivate),
+            Self::Remove => Some(ext_workspace_handle_v1::WorkspaceCapabilities::Remove),
+            Self::Assign => Some(ext_workspace_handle_v1::WorkspaceCapabilities::Assign),
+            Self::Rename | Self::Pin | Self::Move => None,
+        }
+    }
+
+    fn cosmic(self) -> Option<zcosmic_workspace_handle_v2::WorkspaceCapabilities> {
+        match self {
+            Self::Rename => Some(zcosmic_workspace_handle_v2::WorkspaceCapabilities::Rename),
+            Self::Pin => Some(zcosmic_workspace_handle_v2::WorkspaceCapabilities::Pin),
+            Self::Move => Some(zcosmic_workspace_handle_v2::WorkspaceCapabilities::Move),
+            Self::Activate | Self::Deactivate | Self::Remove | Self::Assign => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ToplevelScenario {
+    /// Identifier used to refer to the toplevel in events
+    pub id: Option<String>,
+    pub title: String,
+    pub app_id: String,
+    pub states: Vec<ToplevelState>,
+    /// Color of capture image
+    pub color: (u8, u8, u8),
+    /// Position and size on output, as `(x, y, width, height)`. Cascaded by default.
+    pub geometry: Option<(i32, i32, i32, i32)>,
+}
+
+impl Default for ToplevelScenario {
+    fn default() -> Self {
+        Self {
+            id: None,
+            title: String::new(),
+            app_id: "com.example.app".to_string(),
+            states: Vec::new(),
+            color: (255, 0, 0),
+            geometry: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum ToplevelState {
+    Maximized,
+    Minimized,
+    Activated,
+    Fullscreen,
+    Sticky,
+}
+
+impl From<ToplevelState> for zcosmic_toplevel_handle_v1::State {
+    fn from(state: ToplevelState) -> Self {
+        match state {
+            ToplevelState::Maximized => Self::Maximized,
+            ToplevelState::Minimized => Self::Minimized,
+            ToplevelState::Activated => Self::Activated,
+            ToplevelState::Fullscreen => Self::Fullscreen,
+            ToplevelState::Sticky => Self::Sticky,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TimedEvent {
+    /// Delay after backend is started
+    pub after_ms: u64,
+    pub event: ScenarioEvent,
+}
+
+/// Outputs are referred to by the order they were added, and workspaces by index
+/// on that output.
+#[derive(Clone, Debug, Deserialize)]
+pub enum ScenarioEvent {
+    Open {
+        output: usize,
+        workspace: usize,
+        toplevel: ToplevelScenario,
+    },
+    Close {
+        id: String,
+    },
+    Retitle {
+        id: String,
+        title: String,
+    },
+    Move {
+        id: String,
+        output: usize,
+        workspace: usize,
+    },
+}
@@ -5,39 +5,194 @@
 //! sending commands to change them.
 //!
 //! There are two backends: one that uses cosmic-comp protocols, and a mock
-//! backend for testing without any special protocols.
+//! backend for testing without any special protocols. Which one is used is
+//! chosen at startup, with `--backend`.
 
+use cosmic::cctk::cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1;
 use cosmic::cctk::cosmic_protocols::toplevel_management::v1::client::zcosmic_toplevel_manager_v1;
+use cosmic::cctk::cosmic_protocols::workspace::v2::client::zcosmic_workspace_handle_v2;
 use cosmic::cctk::wayland_client::protocol::wl_output;
+use cosmic::cctk::wayland_client::{Connection, Proxy};
+use cosmic::iced;
 use cosmic::iced::platform_specific::shell::subsurface_widget::SubsurfaceBuffer;
-use std::collections::HashSet;
-
-// Wayland backend using cosmic-comp specific protocols
-#[cfg(not(feature = "mock-backend"))]
-mod wayland;
-#[cfg(not(feature = "mock-backend"))]
-pub use cosmic::cctk::{toplevel_info::ToplevelInfo, workspace::Workspace};
-#[cfg(not(feature = "mock-backend"))]
-pub use wayland_protocols::ext::{
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use wayland_protocols::ext::{
     foreign_toplevel_list::v1::client::ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
-    workspace::v1::client::ext_workspace_handle_v1::ExtWorkspaceHandleV1,
+    workspace::v1::client::{
+        ext_workspace_group_handle_v1,
+        ext_workspace_handle_v1::{self, ExtWorkspaceHandleV1},
+    },
 };
 
-#[cfg(not(feature = "mock-backend"))]
-pub use wayland::subscription;
+// Wayland backend using cosmic-comp specific protocols
+mod wayland;
 
 // Mock backend
-#[cfg(feature = "mock-backend")]
 mod mock;
-#[cfg(feature = "mock-backend")]
-pub use mock::{
-    ExtForeignToplevelHandleV1, ExtWorkspaceHandleV1, ToplevelInfo, Workspace, subscription,
-};
+use mock::MockObjectId;
+
+/// Backend to use, chosen at startup
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum BackendKind {
+    #[default]
+    Wayland,
+    Mock,
+}
+
+pub fn subscription(kind: BackendKind, conn: Connection) -> iced::Subscription<Event> {
+    match kind {
+        BackendKind::Wayland => wayland::subscription(conn),
+        BackendKind::Mock => mock::subscription(conn),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WorkspaceHandle {
+    Wayland(ExtWorkspaceHandleV1),
+    Mock(MockObjectId),
+}
+
+impl WorkspaceHandle {
+    #[cfg(test)]
+    pub fn new_mock() -> Self {
+        Self::Mock(MockObjectId::new())
+    }
+
+    pub fn protocol_id(&self) -> u32 {
+        match self {
+            Self::Wayland(handle) => handle.id().protocol_id(),
+            Self::Mock(id) => id.protocol_id(),
+        }
+    }
+
+    fn wayland(&self) -> Option<&ExtWorkspaceHandleV1> {
+        match self {
+            Self::Wayland(handle) => Some(handle),
+            Self::Mock(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ToplevelHandle {
+    Wayland(ExtForeignToplevelHandleV1),
+    Mock(MockObjectId),
+}
+
+impl ToplevelHandle {
+    #[cfg(test)]
+    pub fn new_mock() -> Self {
+        Self::Mock(MockObjectId::new())
+    }
+
+    pub fn protocol_id(&self) -> u32 {
+        match self {
+            Self::Wayland(handle) => handle.id().protocol_id(),
+            Self::Mock(id) => id.protocol_id(),
+        }
+    }
+
+    fn wayland(&self) -> Option<&ExtForeignToplevelHandleV1> {
+        match self {
+            Self::Wayland(handle) => Some(handle),
+            Self::Mock(_) => None,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Default)]
+pub struct ToplevelInfo {
+    pub title: String,
+    pub app_id: String,
+    pub state: HashSet<zcosmic_toplevel_handle_v1::State>,
+    pub output: HashSet<wl_output::WlOutput>,
+    pub workspace: HashSet<WorkspaceHandle>,
+    pub geometry: HashMap<wl_output::WlOutput, ToplevelGeometry>,
+}
+
+/// Position and size of a toplevel, in logical coordinates relative to an output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ToplevelGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Workspace {
+    pub handle: WorkspaceHandle,
+    pub name: String,
+    pub coordinates: Vec<u32>,
+    pub state: ext_workspace_handle_v1::State,
+    pub capabilities: ext_workspace_handle_v1::WorkspaceCapabilities,
+    pub cosmic_capabilities: zcosmic_workspace_handle_v2::WorkspaceCapabilities,
+    pub cosmic_state: zcosmic_workspace_handle_v2::State,
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkspaceGroup {
+    pub outputs: HashSet<wl_output::WlOutput>,
+    pub capabilities: ext_workspace_group_handle_v1::GroupCapabilities,
+}
+
+/// How often a preview is captured again, after a frame is ready
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CaptureRate {
+    /// Every frame the compositor has damage for
+    Full,
+    /// At most this many frames per second
+    Fps(u32),
+    /// Only once, when capturing starts
+    Snapshot,
+}
+
+impl CaptureRate {
+    /// Minimum time between frames, or `None` if not captured again
+    pub fn interval(self) -> Option<Duration> {
+        match self {
+            Self::Full => Some(Duration::ZERO),
+            Self::Fps(fps) => Some(Duration::from_secs(1) / fps.max(1)),
+            Self::Snapshot => None,
+        }
+    }
+}
+
+/// Capture rate for each kind of preview
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CaptureRates {
+    /// Toplevel previews that are hovered or have keyboard focus
+    pub active_toplevel: CaptureRate,
+    /// Other toplevel previews
+    pub toplevel: CaptureRate,
+    /// Workspace previews in the sidebar
+    pub workspace: CaptureRate,
+}
+
+impl Default for CaptureRates {
+    fn default() -> Self {
+        Self {
+            active_toplevel: CaptureRate::Full,
+            toplevel: CaptureRate::Fps(10),
+            workspace: CaptureRate::Snapshot,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct CaptureFilter {
     pub workspaces_on_outputs: Vec<wl_output::WlOutput>,
-    pub toplevels_on_workspaces: Vec<ExtWorkspaceHandleV1>,
+    pub toplevels_on_workspaces: Vec<WorkspaceHandle>,
+    /// Toplevels captured at `CaptureRates::active_toplevel`
+    pub active_toplevels: Vec<ToplevelHandle>,
+    pub rates: CaptureRates,
+    /// Size each toplevel preview is shown at, in buffer pixels
+    pub toplevel_sizes: Vec<(ToplevelHandle, (u32, u32))>,
+    /// Largest size workspace previews are shown at, in buffer pixels
+    pub workspace_size: Option<(u32, u32)>,
 }
 
 impl CaptureFilter {
@@ -59,42 +214,73 @@ impl CaptureFilter {
 
 #[derive(Clone, Debug)]
 pub struct CaptureImage {
-    #[allow(dead_code)]
     pub width: u32,
-    #[allow(dead_code)]
     pub height: u32,
+    /// Size of a pixel in the buffer's format
+    pub bytes_per_pixel: u32,
     pub wl_buffer: SubsurfaceBuffer,
     pub transform: wl_output::Transform,
     #[cfg(feature = "no-subsurfaces")]
     pub image: cosmic::widget::image::Handle,
 }
 
+impl CaptureImage {
+    #[cfg(test)]
+    pub fn new_mock() -> Self {
+        mock::create_solid_capture_image(0, 0, 0)
+    }
+
+    /// Approximate memory used by the image
+    pub fn size_in_bytes(&self) -> usize {
+        self.width as usize * self.height as usize * self.bytes_per_pixel as usize
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Event {
     CmdSender(calloop::channel::Sender<Cmd>),
+    /// Sent before `Workspaces`, when groups may have changed
+    WorkspaceGroups(Vec<WorkspaceGroup>),
     Workspaces(Vec<(HashSet<wl_output::WlOutput>, Workspace)>),
-    WorkspaceCapture(ExtWorkspaceHandleV1, CaptureImage),
-    NewToplevel(ExtForeignToplevelHandleV1, ToplevelInfo),
-    UpdateToplevel(ExtForeignToplevelHandleV1, ToplevelInfo),
-    CloseToplevel(ExtForeignToplevelHandleV1),
-    ToplevelCapture(ExtForeignToplevelHandleV1, CaptureImage),
+    WorkspaceCapture(WorkspaceHandle, CaptureImage),
+    /// New toplevel, and whether it already existed when the backend first
+    /// reported toplevels, rather than being a window that just spawned
+    NewToplevel(ToplevelHandle, ToplevelInfo, bool),
+    UpdateToplevel(ToplevelHandle, ToplevelInfo),
+    CloseToplevel(ToplevelHandle),
+    ToplevelCapture(ToplevelHandle, CaptureImage),
     ToplevelCapabilities(
         Vec<zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1>,
     ),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Cmd {
     CaptureFilter(CaptureFilter),
-    ActivateToplevel(ExtForeignToplevelHandleV1),
-    CloseToplevel(ExtForeignToplevelHandleV1),
-    MoveToplevelToWorkspace(
-        ExtForeignToplevelHandleV1,
-        ExtWorkspaceHandleV1,
+    ActivateToplevel(ToplevelHandle),
+    CloseToplevel(ToplevelHandle),
+    SetToplevelMinimized(ToplevelHandle, bool),
+    SetToplevelMaximized(ToplevelHandle, bool),
+    SetToplevelFullscreen(ToplevelHandle, bool),
+    /// Show toplevel on all workspaces
+    SetToplevelSticky(ToplevelHandle, bool),
+    MoveToplevelToWorkspace(ToplevelHandle, WorkspaceHandle, wl_output::WlOutput),
+    /// Create workspace, with a name, in the workspace group of the output, and
+    /// move the toplevel to it once it exists
+    MoveToplevelToNewWorkspace(ToplevelHandle, wl_output::WlOutput, String),
+    MoveWorkspaceBefore(WorkspaceHandle, WorkspaceHandle),
+    MoveWorkspaceAfter(WorkspaceHandle, WorkspaceHandle),
+    /// Move workspace to the workspace group of the output, before the given
+    /// workspace in that group, or at the end
+    MoveWorkspaceToOutput(
+        WorkspaceHandle,
         wl_output::WlOutput,
+        Option<WorkspaceHandle>,
     ),
-    MoveWorkspaceBefore(ExtWorkspaceHandleV1, ExtWorkspaceHandleV1),
-    MoveWorkspaceAfter(ExtWorkspaceHandleV1, ExtWorkspaceHandleV1),
-    ActivateWorkspace(ExtWorkspaceHandleV1),
-    SetWorkspacePinned(ExtWorkspaceHandleV1, bool),
+    ActivateWorkspace(WorkspaceHandle),
+    SetWorkspacePinned(WorkspaceHandle, bool),
+    RenameWorkspace(WorkspaceHandle, String),
+    /// Create workspace, with a name, in the workspace group of the output
+    CreateWorkspace(wl_output::WlOutput, String),
+    RemoveWorkspace(WorkspaceHandle),
 }
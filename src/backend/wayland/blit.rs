@@ -0,0 +1,504 @@
+//! Scale dmabufs down on the GPU with a Vulkan blit, so a capture much larger
+//! than its preview isn't shown at full size.
+//!
+//! Buffers are imported for each blit, and released to the compositor when it
+//! is done. Blits are recorded on the event thread, then submitted and waited
+//! for on a worker thread, since the compositor's copy into the source has to
+//! finish first.
+
+use ash::prelude::VkResult;
+use ash::vk;
+use cosmic::iced::platform_specific::shell::subsurface_widget::Dmabuf;
+use rustix::event::{PollFd, PollFlags, Timespec};
+use std::ffi::CStr;
+use std::os::fd::{AsRawFd, IntoRawFd, OwnedFd};
+use std::slice;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::vulkan::{Instance, has_extension};
+
+const DEVICE_EXTENSIONS: &[&CStr] = &[
+    ash::khr::external_memory_fd::NAME,
+    ash::ext::external_memory_dma_buf::NAME,
+    ash::khr::image_format_list::NAME,
+    ash::ext::image_drm_format_modifier::NAME,
+    ash::ext::queue_family_foreign::NAME,
+];
+
+/// Longest to wait for the compositor's write to the source, and for the blit,
+/// before assuming the GPU is hung
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+const COLOR_RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+    aspect_mask: vk::ImageAspectFlags::COLOR,
+    base_mip_level: 0,
+    level_count: 1,
+    base_array_layer: 0,
+    layer_count: 1,
+};
+
+const COLOR_LAYERS: vk::ImageSubresourceLayers = vk::ImageSubresourceLayers {
+    aspect_mask: vk::ImageAspectFlags::COLOR,
+    mip_level: 0,
+    base_array_layer: 0,
+    layer_count: 1,
+};
+
+/// Vulkan format with the same memory layout as the DRM format `fourcc`
+fn vk_format(fourcc: u32) -> Option<vk::Format> {
+    Some(match &fourcc.to_le_bytes() {
+        b"AR24" | b"XR24" => vk::Format::B8G8R8A8_UNORM,
+        b"AB24" | b"XB24" => vk::Format::R8G8B8A8_UNORM,
+        b"AR30" | b"XR30" => vk::Format::A2R10G10B10_UNORM_PACK32,
+        b"AB30" | b"XB30" => vk::Format::A2B10G10R10_UNORM_PACK32,
+        b"AB4H" | b"XB4H" => vk::Format::R16G16B16A16_SFLOAT,
+        _ => return None,
+    })
+}
+
+fn image_barrier(
+    image: vk::Image,
+    (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+    (src_queue_family, dst_queue_family): (u32, u32),
+) -> vk::ImageMemoryBarrier<'static> {
+    vk::ImageMemoryBarrier::default()
+        .image(image)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_queue_family_index(src_queue_family)
+        .dst_queue_family_index(dst_queue_family)
+        .subresource_range(COLOR_RANGE)
+}
+
+pub struct Blitter {
+    device: ash::Device,
+    physical_device: vk::PhysicalDevice,
+    queue: Mutex<vk::Queue>,
+    queue_family: u32,
+    external_memory_fd: ash::khr::external_memory_fd::Device,
+    instance: Arc<Instance>,
+}
+
+impl Blitter {
+    /// Create a device for blitting on `physical_device`, or `None` if it can't
+    /// import dmabufs
+    pub fn new(
+        instance: Arc<Instance>,
+        physical_device: vk::PhysicalDevice,
+    ) -> VkResult<Option<Self>> {
+        let supported = unsafe {
+            instance
+                .instance
+                .enumerate_device_extension_properties(physical_device)?
+        };
+        if !DEVICE_EXTENSIONS
+            .iter()
+            .all(|name| has_extension(&supported, name))
+        {
+            return Ok(None);
+        }
+
+        // Blits need a graphics queue
+        let queue_families = unsafe {
+            instance
+                .instance
+                .get_physical_device_queue_family_properties(physical_device)
+        };
+        let Some(queue_family) = queue_families
+            .iter()
+            .position(|props| props.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+        else {
+            return Ok(None);
+        };
+        let queue_family = queue_family as u32;
+
+        let priorities = [1.];
+        let queue_info = vk::DeviceQueueCreateInfo::default()
+            .queue_family_index(queue_family)
+            .queue_priorities(&priorities);
+        let extensions = DEVICE_EXTENSIONS
+            .iter()
+            .map(|name| name.as_ptr())
+            .collect::<Vec<_>>();
+        let create_info = vk::DeviceCreateInfo::default()
+            .queue_create_infos(slice::from_ref(&queue_info))
+            .enabled_extension_names(&extensions);
+        let device = unsafe {
+            instance
+                .instance
+                .create_device(physical_device, &create_info, None)?
+        };
+        let queue = unsafe { device.get_device_queue(queue_family, 0) };
+        let external_memory_fd =
+            ash::khr::external_memory_fd::Device::new(&instance.instance, &device);
+
+        Ok(Some(Self {
+            device,
+            physical_device,
+            queue: Mutex::new(queue),
+            queue_family,
+            external_memory_fd,
+            instance,
+        }))
+    }
+
+    /// Modifiers supporting `features` with `format`
+    fn modifiers(&self, format: vk::Format, features: vk::FormatFeatureFlags) -> Vec<u64> {
+        let instance = &self.instance.instance;
+        let mut list = vk::DrmFormatModifierPropertiesListEXT::default();
+        let mut props = vk::FormatProperties2::default().push_next(&mut list);
+        unsafe {
+            instance.get_physical_device_format_properties2(
+                self.physical_device,
+                format,
+                &mut props,
+            )
+        };
+        let mut modifier_props = vec![
+            vk::DrmFormatModifierPropertiesEXT::default();
+            list.drm_format_modifier_count as usize
+        ];
+        let mut list = vk::DrmFormatModifierPropertiesListEXT::default()
+            .drm_format_modifier_properties(&mut modifier_props);
+        let mut props = vk::FormatProperties2::default().push_next(&mut list);
+        unsafe {
+            instance.get_physical_device_format_properties2(
+                self.physical_device,
+                format,
+                &mut props,
+            )
+        };
+        modifier_props
+            .iter()
+            .filter(|props| props.drm_format_modifier_tiling_features.contains(features))
+            .map(|props| props.drm_format_modifier)
+            .collect()
+    }
+
+    /// `dmabuf` can be scaled down by `blit`
+    pub fn can_blit_from(&self, dmabuf: &Dmabuf) -> bool {
+        dmabuf.planes.len() == 1
+            && vk_format(dmabuf.format).is_some_and(|format| {
+                self.modifiers(
+                    format,
+                    vk::FormatFeatureFlags::BLIT_SRC
+                        | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+                )
+                .contains(&dmabuf.modifier)
+            })
+    }
+
+    /// Modifiers a buffer in the DRM format `fourcc` can be allocated with, to
+    /// `blit` into
+    pub fn dst_modifiers(&self, fourcc: u32) -> Vec<u64> {
+        vk_format(fourcc).map_or_else(Vec::new, |format| {
+            self.modifiers(format, vk::FormatFeatureFlags::BLIT_DST)
+        })
+    }
+
+    /// Record writing a copy of `src` scaled down by `factor` to `dst`, with
+    /// bilinear filtering, to run with `PendingBlit::submit_and_wait`
+    ///
+    /// `dst` needs to be at most `1 / factor` the size of `src`, in the same
+    /// format.
+    pub fn blit(
+        self: &Arc<Self>,
+        src: &Dmabuf,
+        dst: &Dmabuf,
+        factor: u32,
+    ) -> VkResult<PendingBlit> {
+        let device = &self.device;
+        let [src_plane] = src.planes.as_slice() else {
+            return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED);
+        };
+        let mut pending = PendingBlit {
+            blitter: self.clone(),
+            src_fd: src_plane
+                .fd
+                .try_clone()
+                .map_err(|_| vk::Result::ERROR_TOO_MANY_OBJECTS)?,
+            images: Vec::new(),
+            memories: Vec::new(),
+            command_pool: vk::CommandPool::null(),
+            command_buffer: vk::CommandBuffer::null(),
+            fence: vk::Fence::null(),
+            submitted: false,
+        };
+        let src_image = self.import(&mut pending, src, vk::ImageUsageFlags::TRANSFER_SRC)?;
+        let dst_image = self.import(&mut pending, dst, vk::ImageUsageFlags::TRANSFER_DST)?;
+
+        unsafe {
+            pending.command_pool = device.create_command_pool(
+                &vk::CommandPoolCreateInfo::default().queue_family_index(self.queue_family),
+                None,
+            )?;
+            pending.command_buffer = device.allocate_command_buffers(
+                &vk::CommandBufferAllocateInfo::default()
+                    .command_pool(pending.command_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1),
+            )?[0];
+            let command_buffer = pending.command_buffer;
+            device.begin_command_buffer(
+                command_buffer,
+                &vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            )?;
+
+            // Acquire buffers from the compositor. The layout it left them in isn't
+            // known, but the content of a buffer acquired from the foreign queue
+            // family is kept; the content of `dst` is replaced anyway.
+            let foreign = vk::QUEUE_FAMILY_FOREIGN_EXT;
+            let acquire = [
+                image_barrier(
+                    src_image,
+                    (
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    ),
+                    (foreign, self.queue_family),
+                )
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ),
+                image_barrier(
+                    dst_image,
+                    (
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    ),
+                    (foreign, self.queue_family),
+                )
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE),
+            ];
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &acquire,
+            );
+
+            let factor = factor as i32;
+            let region = vk::ImageBlit {
+                src_subresource: COLOR_LAYERS,
+                src_offsets: [
+                    vk::Offset3D::default(),
+                    vk::Offset3D {
+                        x: dst.width * factor,
+                        y: dst.height * factor,
+                        z: 1,
+                    },
+                ],
+                dst_subresource: COLOR_LAYERS,
+                dst_offsets: [
+                    vk::Offset3D::default(),
+                    vk::Offset3D {
+                        x: dst.width,
+                        y: dst.height,
+                        z: 1,
+                    },
+                ],
+            };
+            device.cmd_blit_image(
+                command_buffer,
+                src_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+                vk::Filter::LINEAR,
+            );
+
+            // Release buffers back to the compositor
+            let release = [
+                image_barrier(
+                    src_image,
+                    (
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::ImageLayout::GENERAL,
+                    ),
+                    (self.queue_family, foreign),
+                )
+                .src_access_mask(vk::AccessFlags::TRANSFER_READ),
+                image_barrier(
+                    dst_image,
+                    (
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::GENERAL,
+                    ),
+                    (self.queue_family, foreign),
+                )
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE),
+            ];
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &release,
+            );
+            device.end_command_buffer(command_buffer)?;
+
+            pending.fence = device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+        }
+
+        Ok(pending)
+    }
+
+    /// Import single plane `dmabuf` as an image, freed with `pending`
+    fn import(
+        &self,
+        pending: &mut PendingBlit,
+        dmabuf: &Dmabuf,
+        usage: vk::ImageUsageFlags,
+    ) -> VkResult<vk::Image> {
+        let device = &self.device;
+        let format = vk_format(dmabuf.format).ok_or(vk::Result::ERROR_FORMAT_NOT_SUPPORTED)?;
+        let [plane] = dmabuf.planes.as_slice() else {
+            return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED);
+        };
+
+        let plane_layout = vk::SubresourceLayout {
+            offset: plane.offset.into(),
+            row_pitch: plane.stride.into(),
+            ..Default::default()
+        };
+        let mut modifier_info = vk::ImageDrmFormatModifierExplicitCreateInfoEXT::default()
+            .drm_format_modifier(dmabuf.modifier)
+            .plane_layouts(slice::from_ref(&plane_layout));
+        let mut external_info = vk::ExternalMemoryImageCreateInfo::default()
+            .handle_types(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT);
+        let create_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: dmabuf.width as u32,
+                height: dmabuf.height as u32,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .push_next(&mut modifier_info)
+            .push_next(&mut external_info);
+        let image = unsafe { device.create_image(&create_info, None)? };
+        pending.images.push(image);
+
+        let fd = plane
+            .fd
+            .try_clone()
+            .map_err(|_| vk::Result::ERROR_TOO_MANY_OBJECTS)?;
+        let mut fd_props = vk::MemoryFdPropertiesKHR::default();
+        unsafe {
+            self.external_memory_fd.get_memory_fd_properties(
+                vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
+                fd.as_raw_fd(),
+                &mut fd_props,
+            )?
+        };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+        let memory_type_bits = requirements.memory_type_bits & fd_props.memory_type_bits;
+        if memory_type_bits == 0 {
+            return Err(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE);
+        }
+
+        let mut import_info = vk::ImportMemoryFdInfoKHR::default()
+            .handle_type(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT)
+            .fd(fd.as_raw_fd());
+        let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::default().image(image);
+        let allocate_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(requirements.size)
+            .memory_type_index(memory_type_bits.trailing_zeros())
+            .push_next(&mut import_info)
+            .push_next(&mut dedicated_info);
+        let memory = unsafe { device.allocate_memory(&allocate_info, None)? };
+        // Owned by the driver once imported
+        let _ = fd.into_raw_fd();
+        pending.memories.push(memory);
+        unsafe { device.bind_image_memory(image, memory, 0)? };
+
+        Ok(image)
+    }
+}
+
+impl Drop for Blitter {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.device.device_wait_idle();
+            self.device.destroy_device(None);
+        }
+    }
+}
+
+/// A recorded blit, and the resources it uses until finished
+pub struct PendingBlit {
+    blitter: Arc<Blitter>,
+    /// Source dmabuf, polled for the compositor's write to finish
+    src_fd: OwnedFd,
+    images: Vec<vk::Image>,
+    memories: Vec<vk::DeviceMemory>,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    submitted: bool,
+}
+
+impl PendingBlit {
+    /// Submit the blit once the compositor has written the source, and block
+    /// until it's finished, waiting at most `TIMEOUT` for each
+    pub fn submit_and_wait(mut self) -> VkResult<()> {
+        // Vulkan doesn't wait for the implicit fence of the compositor's write,
+        // but polling the dmabuf for reading does
+        let timeout = Timespec {
+            tv_sec: TIMEOUT.as_secs() as _,
+            tv_nsec: TIMEOUT.subsec_nanos() as _,
+        };
+        let mut fds = [PollFd::new(&self.src_fd, PollFlags::IN)];
+        match rustix::io::retry_on_intr(|| rustix::event::poll(&mut fds, Some(&timeout))) {
+            Ok(0) => return Err(vk::Result::TIMEOUT),
+            Ok(_) => {}
+            Err(err) => log::warn!("Failed to poll capture dmabuf: {}", err),
+        }
+
+        let device = &self.blitter.device;
+        let submit =
+            vk::SubmitInfo::default().command_buffers(slice::from_ref(&self.command_buffer));
+        unsafe {
+            let queue = self.blitter.queue.lock().unwrap();
+            device.queue_submit(*queue, &[submit], self.fence)?;
+        }
+        self.submitted = true;
+        unsafe { device.wait_for_fences(&[self.fence], true, TIMEOUT.as_nanos() as u64)? };
+        self.submitted = false;
+        Ok(())
+    }
+}
+
+impl Drop for PendingBlit {
+    fn drop(&mut self) {
+        let device = &self.blitter.device;
+        unsafe {
+            // Waiting for the blit failed, so the GPU may still be using the
+            // resources; leak rather than free them unless it has since finished
+            if self.submitted && device.get_fence_status(self.fence) != Ok(true) {
+                return;
+            }
+            device.destroy_fence(self.fence, None);
+            device.destroy_command_pool(self.command_pool, None);
+            for image in &self.images {
+                device.destroy_image(*image, None);
+            }
+            for memory in &self.memories {
+                device.free_memory(*memory, None);
+            }
+        }
+    }
+}
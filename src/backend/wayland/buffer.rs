@@ -6,29 +6,144 @@ use cosmic::iced::platform_specific::shell::subsurface_widget::{
     BufferSource, Dmabuf, Plane, Shmbuf,
 };
 use std::os::fd::AsFd;
+use std::slice;
 use std::sync::Arc;
 use wayland_protocols::wp::linux_dmabuf::zv1::client::zwp_linux_buffer_params_v1;
 
 use super::AppData;
 use crate::utils;
 
+/// Formats to capture to with dmabufs, most preferred first
+///
+/// 8-bit formats come first, since previews don't need more, and FP16 takes
+/// twice the memory. The capture protocol doesn't say if the source is HDR, so
+/// wider formats are only used if the compositor offers no 8-bit format.
+#[cfg(not(feature = "force-shm-screencopy"))]
+const DMABUF_FORMATS: &[wl_shm::Format] = &[
+    wl_shm::Format::Abgr8888,
+    wl_shm::Format::Xbgr8888,
+    wl_shm::Format::Argb8888,
+    wl_shm::Format::Xrgb8888,
+    wl_shm::Format::Abgr2101010,
+    wl_shm::Format::Xbgr2101010,
+    wl_shm::Format::Argb2101010,
+    wl_shm::Format::Xrgb2101010,
+    wl_shm::Format::Abgr16161616f,
+    wl_shm::Format::Xbgr16161616f,
+];
+
+/// Formats to capture to with shm, most preferred first
+///
+/// 8-bit formats come first, since they are copied by the CPU, and can be
+/// downscaled.
+#[cfg(not(feature = "no-subsurfaces"))]
+const SHM_FORMATS: &[wl_shm::Format] = &[
+    wl_shm::Format::Abgr8888,
+    wl_shm::Format::Xbgr8888,
+    wl_shm::Format::Argb8888,
+    wl_shm::Format::Xrgb8888,
+    wl_shm::Format::Abgr2101010,
+    wl_shm::Format::Xbgr2101010,
+    wl_shm::Format::Argb2101010,
+    wl_shm::Format::Xrgb2101010,
+    wl_shm::Format::Abgr16161616f,
+    wl_shm::Format::Xbgr16161616f,
+];
+
+// Image handle is created from the mapped buffer as RGBA
+#[cfg(feature = "no-subsurfaces")]
+const SHM_FORMATS: &[wl_shm::Format] = &[wl_shm::Format::Abgr8888];
+
+/// DRM fourcc with the same layout as `format`
+///
+/// `wl_shm` uses DRM fourcc codes, except for the two formats every compositor
+/// supports, which are 0 and 1.
+pub fn drm_fourcc(format: wl_shm::Format) -> u32 {
+    match format {
+        wl_shm::Format::Argb8888 => u32::from_le_bytes(*b"AR24"),
+        wl_shm::Format::Xrgb8888 => u32::from_le_bytes(*b"XR24"),
+        _ => u32::from(format),
+    }
+}
+
+/// Formats in `DMABUF_FORMATS` the compositor supports, most preferred first,
+/// with their supported modifiers
+#[cfg(not(feature = "force-shm-screencopy"))]
+fn dmabuf_formats(supported: &[(u32, Vec<u64>)]) -> impl Iterator<Item = (wl_shm::Format, &[u64])> {
+    DMABUF_FORMATS.iter().filter_map(|format| {
+        supported
+            .iter()
+            .find(|(fourcc, _)| *fourcc == drm_fourcc(*format))
+            .map(|(_, modifiers)| (*format, modifiers.as_slice()))
+    })
+}
+
+/// Most preferred format in `SHM_FORMATS` the compositor supports
+fn shm_format(supported: &[wl_shm::Format]) -> Option<wl_shm::Format> {
+    SHM_FORMATS
+        .iter()
+        .find(|format| supported.contains(format))
+        .copied()
+}
+
+/// Modifiers a buffer can be allocated with, out of those `supported`
+///
+/// The implicit modifier is only used on the main device, since another GPU
+/// may not agree on the layout it implies.
+#[cfg(not(feature = "force-shm-screencopy"))]
+fn usable_modifiers(
+    supported: &[u64],
+    needs_linear: bool,
+    on_main_device: bool,
+) -> Vec<gbm::Modifier> {
+    supported
+        .iter()
+        .map(|modifier| gbm::Modifier::from(*modifier))
+        .filter(|modifier| !needs_linear || *modifier == gbm::Modifier::Linear)
+        .filter(|modifier| on_main_device || *modifier != gbm::Modifier::Invalid)
+        .collect()
+}
+
+pub fn bytes_per_pixel(format: wl_shm::Format) -> u32 {
+    match format {
+        wl_shm::Format::Abgr16161616f | wl_shm::Format::Xbgr16161616f => 8,
+        _ => 4,
+    }
+}
+
+/// Format has 8 bits per channel, in 4 bytes, so channels can be averaged
+/// byte-by-byte
+pub fn is_8bpc(format: wl_shm::Format) -> bool {
+    matches!(
+        format,
+        wl_shm::Format::Abgr8888
+            | wl_shm::Format::Xbgr8888
+            | wl_shm::Format::Argb8888
+            | wl_shm::Format::Xrgb8888
+    )
+}
+
 pub struct Buffer {
     pub backing: Arc<BufferSource>,
     pub buffer: wl_buffer::WlBuffer,
     pub buffer_damage: Vec<Rect>,
     pub size: (u32, u32),
-    #[cfg(feature = "no-subsurfaces")]
-    pub mmap: memmap2::Mmap,
+    pub format: wl_shm::Format,
+    // DRM device dmabuf is allocated on
+    pub dev: Option<u64>,
+    // Mapping of shm buffer, shared with a job downscaling from or to it
+    pub mmap: Option<Arc<memmap2::MmapRaw>>,
 }
 
 impl AppData {
-    fn create_shm_buffer(&self, format: wl_shm::Format, (width, height): (u32, u32)) -> Buffer {
+    pub fn create_shm_buffer(&self, format: wl_shm::Format, (width, height): (u32, u32)) -> Buffer {
+        let stride = width * bytes_per_pixel(format);
         let fd = utils::create_memfile().unwrap(); // XXX?
-        rustix::fs::ftruncate(&fd, width as u64 * height as u64 * 4).unwrap();
+        rustix::fs::ftruncate(&fd, stride as u64 * height as u64).unwrap();
 
         let pool = self.shm_state.wl_shm().create_pool(
             fd.as_fd(),
-            width as i32 * height as i32 * 4,
+            stride as i32 * height as i32,
             &self.qh,
             (),
         );
@@ -37,7 +152,7 @@ impl AppData {
             0,
             width as i32,
             height as i32,
-            width as i32 * 4,
+            stride as i32,
             format,
             &self.qh,
             (),
@@ -45,8 +160,13 @@ impl AppData {
 
         pool.destroy();
 
-        #[cfg(feature = "no-subsurfaces")]
-        let mmap = unsafe { memmap2::Mmap::map(&fd).unwrap() };
+        let mmap = match memmap2::MmapRaw::map_raw(&fd) {
+            Ok(mmap) => Some(Arc::new(mmap)),
+            Err(err) => {
+                log::error!("Failed to map shm buffer: {}", err);
+                None
+            }
+        };
 
         let full_damage = vec![Rect {
             x: 0,
@@ -62,23 +182,24 @@ impl AppData {
                     offset: 0,
                     width: width as i32,
                     height: height as i32,
-                    stride: width as i32 * 4,
+                    stride: stride as i32,
                     format,
                 }
                 .into(),
             ),
             buffer,
             buffer_damage: full_damage,
-            #[cfg(feature = "no-subsurfaces")]
             mmap,
             size: (width, height),
+            format,
+            dev: None,
         }
     }
 
     #[cfg(not(feature = "force-shm-screencopy"))]
-    fn create_gbm_buffer(
+    pub fn create_gbm_buffer(
         &mut self,
-        format: u32,
+        format: wl_shm::Format,
         modifiers: &[u64],
         (width, height): (u32, u32),
         needs_linear: bool,
@@ -87,29 +208,24 @@ impl AppData {
         let Some(feedback) = self.dmabuf_feedback.as_ref() else {
             return Ok(None);
         };
-        let drm_dev = drm_dev.unwrap_or(feedback.main_device());
-        if let Some(vulkan) = &mut self.vulkan
-            && let Ok(Some(name)) = vulkan.device_name(drm_dev)
-        {
-            // TODO Workaround: force shm on Meteor/Arrow/Lunar Lake
-            if name.contains("MTL") || name.contains("ARL") || name.contains("LNL") {
-                return Ok(None);
-            }
+        let main_dev = feedback.main_device();
+        let drm_dev = drm_dev.unwrap_or(main_dev);
+        let main_quirks = self.quirks.device_quirks(self.vulkan.as_mut(), main_dev);
+        let quirks = self.quirks.device_quirks(self.vulkan.as_mut(), drm_dev);
+        if quirks.force_shm || quirks.disable_dmabuf || main_quirks.disable_dmabuf {
+            return Ok(None);
         }
+        let needs_linear = needs_linear || quirks.force_linear;
         let Some((_dev_path, gbm)) = self.gbm_devices.gbm_device(drm_dev)? else {
             return Ok(None);
         };
 
-        let modifiers = modifiers
-            .iter()
-            .map(|modifier| gbm::Modifier::from(*modifier))
-            .filter(|modifier| !needs_linear || *modifier == gbm::Modifier::Linear)
-            .collect::<Vec<_>>();
-
+        let modifiers = usable_modifiers(modifiers, needs_linear, drm_dev == main_dev);
         if modifiers.is_empty() {
             return Ok(None);
         };
-        let gbm_format = gbm::Format::try_from(format)?;
+        let fourcc = drm_fourcc(format);
+        let gbm_format = gbm::Format::try_from(fourcc)?;
         //dbg!(format, modifiers);
         let bo = if !modifiers.iter().all(|x| *x == gbm::Modifier::Invalid) {
             gbm.create_buffer_object_with_modifiers::<()>(
@@ -119,7 +235,6 @@ impl AppData {
                 modifiers.iter().copied(),
             )?
         } else {
-            // TODO make sure this isn't used across different GPUs
             gbm.create_buffer_object::<()>(
                 width,
                 height,
@@ -154,7 +269,7 @@ impl AppData {
             .create_immed(
                 width as i32,
                 height as i32,
-                format,
+                fourcc,
                 zwp_linux_buffer_params_v1::Flags::empty(),
                 &self.qh,
             )
@@ -173,7 +288,7 @@ impl AppData {
                     width: width as i32,
                     height: height as i32,
                     planes,
-                    format,
+                    format: fourcc,
                     modifier: modifier.into(),
                 }
                 .into(),
@@ -181,38 +296,103 @@ impl AppData {
             buffer,
             buffer_damage: full_damage,
             size: (width, height),
+            format,
+            dev: Some(drm_dev),
+            mmap: None,
         }))
     }
 
-    pub fn create_buffer(&mut self, formats: &Formats) -> Buffer {
-        // XXX Handle other formats?
-        let format = wl_shm::Format::Abgr8888;
-
+    /// Create buffer in the most preferred format supported by the compositor, or
+    /// `None` if no format is supported
+    pub fn create_buffer(&mut self, formats: &Formats) -> Option<Buffer> {
         #[cfg(not(feature = "force-shm-screencopy"))]
-        if let Some((_, modifiers)) = formats
-            .dmabuf_formats
-            .iter()
-            .find(|(f, _)| *f == u32::from(format))
-        {
+        for (format, modifiers) in dmabuf_formats(&formats.dmabuf_formats) {
             match self.create_gbm_buffer(
-                u32::from(format),
+                format,
                 modifiers,
                 formats.buffer_size,
                 false,
                 formats.dmabuf_device,
             ) {
                 Ok(Some(buffer)) => {
-                    return buffer;
+                    return Some(buffer);
                 }
+                // No modifier supported, or dmabufs not used on this device
                 Ok(None) => {}
-                Err(err) => log::error!("Failed to create gbm buffer: {}", err),
+                Err(err) => log::error!("Failed to create gbm buffer for {:?}: {}", format, err),
             }
         }
 
         // Fallback to shm buffer
-        // Assume format is already known to be valid
-        assert!(formats.shm_formats.contains(&format));
-        self.create_shm_buffer(format, formats.buffer_size)
+        let Some(format) = shm_format(&formats.shm_formats) else {
+            log::error!("No supported capture format in {:?}", formats.shm_formats);
+            return None;
+        };
+        Some(self.create_shm_buffer(format, formats.buffer_size))
+    }
+}
+
+impl Buffer {
+    pub fn is_dmabuf(&self) -> bool {
+        matches!(*self.backing, BufferSource::Dma(_))
+    }
+}
+
+/// Contents of a mapped shm buffer
+///
+/// # Safety
+///
+/// The buffer can't be written while the slice is used, by the compositor
+/// capturing into it, or a job downscaling to it.
+pub unsafe fn mapped(mmap: &memmap2::MmapRaw) -> &[u8] {
+    unsafe { slice::from_raw_parts(mmap.as_ptr(), mmap.len()) }
+}
+
+/// Work to write a copy of shm buffer `src` scaled down by `factor` to `dst`,
+/// averaging each `factor` by `factor` block of pixels
+///
+/// Both buffers need to be in the same 8-bit format, and `dst` at most
+/// `1 / factor` the size of `src`. Neither can be captured into or shown until
+/// the work is done.
+pub fn downscale_job(src: &Buffer, dst: &Buffer, factor: u32) -> Option<impl FnOnce() + Send> {
+    let (Some(src_mmap), Some(dst_mmap)) = (src.mmap.clone(), dst.mmap.clone()) else {
+        return None;
+    };
+    if dst.format != src.format || !is_8bpc(src.format) {
+        return None;
+    }
+    let (src_width, dst_size) = (src.size.0, dst.size);
+    Some(move || {
+        let src = unsafe { mapped(&src_mmap) };
+        let dst = unsafe { slice::from_raw_parts_mut(dst_mmap.as_mut_ptr(), dst_mmap.len()) };
+        downscale(src, src_width, dst, dst_size, factor);
+    })
+}
+
+fn downscale(src: &[u8], src_width: u32, dst: &mut [u8], (width, height): (u32, u32), factor: u32) {
+    let factor = factor as usize;
+    let count = (factor * factor) as u32;
+    let src_stride = src_width as usize * 4;
+    let dst_stride = width as usize * 4;
+    for (y, dst_row) in dst
+        .chunks_exact_mut(dst_stride)
+        .take(height as usize)
+        .enumerate()
+    {
+        let src_rows = &src[y * factor * src_stride..][..factor * src_stride];
+        for (x, dst_pixel) in dst_row.chunks_exact_mut(4).enumerate() {
+            let mut sum = [0u32; 4];
+            for src_row in src_rows.chunks_exact(src_stride) {
+                for src_pixel in src_row[x * factor * 4..][..factor * 4].chunks_exact(4) {
+                    for (sum, channel) in sum.iter_mut().zip(src_pixel) {
+                        *sum += u32::from(*channel);
+                    }
+                }
+            }
+            for (channel, sum) in dst_pixel.iter_mut().zip(sum) {
+                *channel = (sum / count) as u8;
+            }
+        }
     }
 }
 
@@ -249,3 +429,104 @@ impl Dispatch<wl_shm_pool::WlShmPool, ()> for AppData {
     ) {
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drm_fourcc_of_shm_formats() {
+        let fourcc = |code: &[u8; 4]| u32::from_le_bytes(*code);
+        assert_eq!(drm_fourcc(wl_shm::Format::Argb8888), fourcc(b"AR24"));
+        assert_eq!(drm_fourcc(wl_shm::Format::Xrgb8888), fourcc(b"XR24"));
+        assert_eq!(drm_fourcc(wl_shm::Format::Abgr8888), fourcc(b"AB24"));
+        assert_eq!(drm_fourcc(wl_shm::Format::Xbgr2101010), fourcc(b"XB30"));
+        assert_eq!(drm_fourcc(wl_shm::Format::Abgr16161616f), fourcc(b"AB4H"));
+    }
+
+    #[cfg(not(feature = "force-shm-screencopy"))]
+    #[test]
+    fn dmabuf_format_prefers_8bpc() {
+        let linear = u64::from(gbm::Modifier::Linear);
+        let supported = vec![
+            (drm_fourcc(wl_shm::Format::Abgr16161616f), vec![linear]),
+            (drm_fourcc(wl_shm::Format::Xbgr2101010), vec![linear]),
+            (drm_fourcc(wl_shm::Format::Xrgb8888), vec![linear]),
+            (drm_fourcc(wl_shm::Format::Argb8888), vec![linear]),
+            // Unknown format
+            (u32::from_le_bytes(*b"NV12"), vec![linear]),
+        ];
+        let formats = dmabuf_formats(&supported)
+            .map(|(format, _)| format)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            formats,
+            [
+                wl_shm::Format::Argb8888,
+                wl_shm::Format::Xrgb8888,
+                wl_shm::Format::Xbgr2101010,
+                wl_shm::Format::Abgr16161616f,
+            ]
+        );
+
+        // `wl_shm` codes of Argb8888 and Xrgb8888 aren't DRM formats
+        let supported = vec![(0, vec![linear]), (1, vec![linear])];
+        assert_eq!(dmabuf_formats(&supported).count(), 0);
+    }
+
+    #[cfg(not(feature = "no-subsurfaces"))]
+    #[test]
+    fn shm_format_prefers_8bpc() {
+        let supported = [
+            wl_shm::Format::Abgr16161616f,
+            wl_shm::Format::Xrgb2101010,
+            wl_shm::Format::Xrgb8888,
+        ];
+        assert_eq!(shm_format(&supported), Some(wl_shm::Format::Xrgb8888));
+        assert_eq!(
+            shm_format(&supported[..2]),
+            Some(wl_shm::Format::Xrgb2101010)
+        );
+        assert_eq!(shm_format(&[wl_shm::Format::Rgb565]), None);
+    }
+
+    #[cfg(not(feature = "force-shm-screencopy"))]
+    #[test]
+    fn usable_modifiers_filtered() {
+        let linear = u64::from(gbm::Modifier::Linear);
+        let invalid = u64::from(gbm::Modifier::Invalid);
+        let tiled = u64::from(gbm::Modifier::I915_x_tiled);
+        let supported = [invalid, tiled, linear];
+        assert_eq!(
+            usable_modifiers(&supported, false, true),
+            [
+                gbm::Modifier::Invalid,
+                gbm::Modifier::I915_x_tiled,
+                gbm::Modifier::Linear
+            ]
+        );
+        assert_eq!(
+            usable_modifiers(&supported, false, false),
+            [gbm::Modifier::I915_x_tiled, gbm::Modifier::Linear]
+        );
+        assert_eq!(
+            usable_modifiers(&supported, true, false),
+            [gbm::Modifier::Linear]
+        );
+        assert!(usable_modifiers(&[invalid], false, false).is_empty());
+    }
+
+    #[test]
+    fn downscale_averages_blocks() {
+        #[rustfmt::skip]
+        let src = [
+            0, 0, 0, 255,  4, 8, 12, 255,  100, 0, 0, 0,  200, 0, 0, 0,
+            8, 0, 4, 255,  4, 8, 0, 255,   100, 0, 0, 0,  200, 0, 0, 0,
+            // Not a full block, so ignored
+            1, 1, 1, 1,    1, 1, 1, 1,     1, 1, 1, 1,    1, 1, 1, 1,
+        ];
+        let mut dst = [0; 8];
+        downscale(&src, 4, &mut dst, (2, 1), 2);
+        assert_eq!(dst, [4, 4, 4, 255, 150, 0, 0, 0]);
+    }
+}
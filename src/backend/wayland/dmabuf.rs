@@ -20,7 +20,15 @@ impl DmabufHandler for AppData {
         _proxy: &ZwpLinuxDmabufFeedbackV1,
         feedback: DmabufFeedback,
     ) {
+        let main_device_changed = self
+            .dmabuf_feedback
+            .as_ref()
+            .is_some_and(|old| old.main_device() != feedback.main_device());
         self.dmabuf_feedback = Some(feedback);
+        if main_device_changed {
+            log::info!("dmabuf main device changed; re-allocating capture buffers");
+            self.reallocate_capture_buffers();
+        }
     }
     fn created(
         &mut self,
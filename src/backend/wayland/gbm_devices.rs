@@ -3,7 +3,6 @@ use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::{fs, io};
 
-// TODO Purge gbm devices that are no longer needed/valid?
 #[derive(Default)]
 pub struct GbmDevices {
     devices: HashMap<u64, (PathBuf, gbm::Device<fs::File>)>,
@@ -26,6 +25,21 @@ impl GbmDevices {
             }
         })
     }
+
+    /// Close devices whose node has been removed, or replaced with a different
+    /// device, returning the device numbers that were closed
+    pub fn purge_stale(&mut self) -> Vec<u64> {
+        let mut removed = Vec::new();
+        self.devices.retain(|dev, (path, _)| {
+            let valid = fs::metadata(&*path).is_ok_and(|metadata| metadata.rdev() == *dev);
+            if !valid {
+                log::info!("Closed gbm device '{}'", path.display());
+                removed.push(*dev);
+            }
+            valid
+        });
+        removed
+    }
 }
 
 fn find_gbm_device(dev: u64) -> io::Result<Option<(PathBuf, gbm::Device<fs::File>)>> {
@@ -0,0 +1,84 @@
+//! Watch `/dev/dri` for DRM devices being added or removed, such as an eGPU
+//! being unplugged, so stale devices aren't used for capture buffers.
+
+use calloop::generic::Generic;
+use calloop::{Interest, Mode, PostAction};
+use rustix::fs::inotify;
+use std::ffi::CStr;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::fd::AsFd;
+
+use super::{AppData, vulkan};
+
+const DRI_DIR: &str = "/dev/dri";
+
+/// `name` is a DRM device node, rather than e.g. the `by-path` directory
+fn is_drm_node(name: &CStr) -> bool {
+    let name = name.to_bytes();
+    name.starts_with(b"card") || name.starts_with(b"renderD")
+}
+
+impl AppData {
+    pub(super) fn watch_drm_devices(&self) -> io::Result<()> {
+        let fd = inotify::init(inotify::CreateFlags::CLOEXEC | inotify::CreateFlags::NONBLOCK)?;
+        inotify::add_watch(
+            &fd,
+            DRI_DIR,
+            inotify::WatchFlags::CREATE | inotify::WatchFlags::DELETE,
+        )?;
+        self.loop_handle
+            .insert_source(
+                Generic::new(fd, Interest::READ, Mode::Level),
+                |_, fd, app_data| {
+                    // Handle all queued events at once
+                    let mut buf = [MaybeUninit::uninit(); 1024];
+                    let mut reader = inotify::Reader::new(fd.as_fd(), &mut buf);
+                    let mut node_added = false;
+                    while let Ok(event) = reader.next() {
+                        node_added |= event.events().contains(inotify::ReadFlags::CREATE)
+                            && event.file_name().is_some_and(is_drm_node);
+                    }
+                    app_data.drm_devices_changed(node_added);
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|err| io::Error::other(err.error))?;
+        Ok(())
+    }
+
+    /// Drop state for removed devices, and pick up new ones if `node_added`
+    fn drm_devices_changed(&mut self, node_added: bool) {
+        let removed = self.gbm_devices.purge_stale();
+        if removed.is_empty() && !node_added {
+            return;
+        }
+        // Physical devices are only enumerated when the instance is created
+        self.vulkan = match vulkan::Vulkan::new() {
+            Ok(vulkan) => Some(vulkan),
+            Err(err) => {
+                log::info!(
+                    "Unable to initialize Vulkan: {}. Assuming no GPU workarounds needed.",
+                    err
+                );
+                None
+            }
+        };
+        self.quirks.clear_cache();
+        if !removed.is_empty() {
+            self.reallocate_capture_buffers();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drm_nodes() {
+        assert!(is_drm_node(c"card1"));
+        assert!(is_drm_node(c"renderD128"));
+        assert!(!is_drm_node(c"by-path"));
+    }
+}
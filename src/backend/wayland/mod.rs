@@ -2,6 +2,8 @@
 // subscription.
 
 use calloop_wayland_source::WaylandSource;
+use cctk::cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1;
+use cctk::cosmic_protocols::toplevel_management::v1::client::zcosmic_toplevel_manager_v1;
 use cctk::cosmic_protocols::workspace::v2::client::zcosmic_workspace_handle_v2;
 use cctk::screencopy::{CaptureSource, ScreencopyState};
 use cctk::sctk::dmabuf::{DmabufFeedback, DmabufState};
@@ -12,7 +14,7 @@ use cctk::sctk::{self};
 use cctk::toplevel_info::ToplevelInfoState;
 use cctk::toplevel_management::ToplevelManagerState;
 use cctk::wayland_client::globals::registry_queue_init;
-use cctk::wayland_client::protocol::wl_seat;
+use cctk::wayland_client::protocol::{wl_output, wl_seat};
 use cctk::wayland_client::{Connection, Proxy, QueueHandle};
 use cctk::workspace::WorkspaceState;
 use cosmic::cctk;
@@ -21,11 +23,15 @@ use cosmic::iced::futures::executor::{ThreadPool, block_on};
 use cosmic::iced::futures::{FutureExt, SinkExt};
 use cosmic::iced::{self};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 use std::sync::Arc;
 use std::thread;
+use wayland_protocols::ext::workspace::v1::client::{
+    ext_workspace_group_handle_v1, ext_workspace_handle_v1,
+};
 
+mod blit;
 mod buffer;
 use buffer::Buffer;
 mod capture;
@@ -33,13 +39,17 @@ use capture::Capture;
 mod dmabuf;
 mod gbm_devices;
 use gbm_devices::GbmDevices;
+mod hotplug;
+mod quirks;
 mod screencopy;
 use screencopy::{ScreencopySession, SessionData};
 mod toplevel;
 mod vulkan;
 mod workspace;
 
-use super::{CaptureFilter, CaptureImage, Cmd, Event};
+use super::{
+    CaptureFilter, CaptureImage, CaptureRate, Cmd, Event, ToplevelHandle, WorkspaceHandle,
+};
 
 pub fn subscription(conn: Connection) -> iced::Subscription<Event> {
     #[derive(Clone)]
@@ -58,8 +68,19 @@ pub fn subscription(conn: Connection) -> iced::Subscription<Event> {
     )
 }
 
+/// Toplevel to move to a workspace requested with `create_workspace`, once the
+/// compositor has added it to the output's group
+struct PendingNewWorkspace {
+    toplevel: ToplevelHandle,
+    output: wl_output::WlOutput,
+    /// Workspaces in the last `Workspaces` event before the request
+    existing: HashSet<ext_workspace_handle_v1::ExtWorkspaceHandleV1>,
+}
+
 pub struct AppData {
+    conn: Connection,
     qh: QueueHandle<Self>,
+    loop_handle: calloop::LoopHandle<'static, Self>,
     dmabuf_state: DmabufState,
     registry_state: RegistryState,
     toplevel_info_state: ToplevelInfoState,
@@ -74,7 +95,13 @@ pub struct AppData {
     dmabuf_feedback: Option<DmabufFeedback>,
     gbm_devices: GbmDevices,
     thread_pool: ThreadPool,
+    /// Runs downscaling jobs, which block, without delaying `thread_pool`
+    downscale_pool: ThreadPool,
     vulkan: Option<vulkan::Vulkan>,
+    quirks: quirks::QuirkTable,
+    pending_new_workspaces: Vec<PendingNewWorkspace>,
+    /// Workspaces sent in the last `Workspaces` event
+    sent_workspaces: HashSet<ext_workspace_handle_v1::ExtWorkspaceHandleV1>,
 }
 
 impl AppData {
@@ -90,8 +117,7 @@ impl AppData {
                 self.invalidate_capture_filter();
             }
             Cmd::ActivateToplevel(toplevel_handle) => {
-                let info = self.toplevel_info_state.info(&toplevel_handle);
-                if let Some(cosmic_toplevel) = info.and_then(|x| x.cosmic_toplevel.as_ref()) {
+                if let Some(cosmic_toplevel) = self.cosmic_toplevel(&toplevel_handle) {
                     for seat in self.seat_state.seats() {
                         if let Some(state) = &self.toplevel_manager_state {
                             state.manager.activate(cosmic_toplevel, &seat);
@@ -100,68 +126,147 @@ impl AppData {
                 }
             }
             Cmd::CloseToplevel(toplevel_handle) => {
-                let info = self.toplevel_info_state.info(&toplevel_handle);
-                if let Some(cosmic_toplevel) = info.and_then(|x| x.cosmic_toplevel.as_ref())
+                if let Some(cosmic_toplevel) = self.cosmic_toplevel(&toplevel_handle)
                     && let Some(state) = &self.toplevel_manager_state
                 {
                     state.manager.close(cosmic_toplevel);
                 }
             }
+            Cmd::SetToplevelMinimized(toplevel_handle, value) => {
+                if let Some(cosmic_toplevel) = self.cosmic_toplevel(&toplevel_handle)
+                    && let Some(state) = &self.toplevel_manager_state
+                {
+                    if value {
+                        state.manager.set_minimized(cosmic_toplevel);
+                    } else {
+                        state.manager.unset_minimized(cosmic_toplevel);
+                    }
+                }
+            }
+            Cmd::SetToplevelMaximized(toplevel_handle, value) => {
+                if let Some(cosmic_toplevel) = self.cosmic_toplevel(&toplevel_handle)
+                    && let Some(state) = &self.toplevel_manager_state
+                {
+                    if value {
+                        state.manager.set_maximized(cosmic_toplevel);
+                    } else {
+                        state.manager.unset_maximized(cosmic_toplevel);
+                    }
+                }
+            }
+            Cmd::SetToplevelFullscreen(toplevel_handle, value) => {
+                if let Some(cosmic_toplevel) = self.cosmic_toplevel(&toplevel_handle)
+                    && let Some(state) = &self.toplevel_manager_state
+                {
+                    if value {
+                        state.manager.set_fullscreen(cosmic_toplevel, None);
+                    } else {
+                        state.manager.unset_fullscreen(cosmic_toplevel);
+                    }
+                }
+            }
+            Cmd::SetToplevelSticky(toplevel_handle, value) => {
+                if let Some(cosmic_toplevel) = self.cosmic_toplevel(&toplevel_handle)
+                    && let Some(state) = &self.toplevel_manager_state
+                    && state.manager.version() >= zcosmic_toplevel_manager_v1::REQ_SET_STICKY_SINCE
+                {
+                    if value {
+                        state.manager.set_sticky(cosmic_toplevel);
+                    } else {
+                        state.manager.unset_sticky(cosmic_toplevel);
+                    }
+                }
+            }
             Cmd::MoveToplevelToWorkspace(toplevel_handle, workspace_handle, output) => {
-                let info = self.toplevel_info_state.info(&toplevel_handle);
-                if let Some(cosmic_toplevel) = info.and_then(|x| x.cosmic_toplevel.as_ref())
+                if let Some(cosmic_toplevel) = self.cosmic_toplevel(&toplevel_handle)
+                    && let Some(workspace_handle) = workspace_handle.wayland()
                     && let Some(state) = &self.toplevel_manager_state
                     && state.manager.version() >= 2
                 {
-                    state.manager.move_to_ext_workspace(
-                        cosmic_toplevel,
-                        &workspace_handle,
-                        &output,
-                    );
+                    state
+                        .manager
+                        .move_to_ext_workspace(cosmic_toplevel, workspace_handle, &output);
+                }
+            }
+            Cmd::MoveToplevelToNewWorkspace(toplevel_handle, output, name) => {
+                if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get()
+                    && let Some(group) = self
+                        .workspace_state
+                        .workspace_groups()
+                        .find(|g| g.outputs.contains(&output))
+                    && group
+                        .capabilities
+                        .contains(ext_workspace_group_handle_v1::GroupCapabilities::CreateWorkspace)
+                {
+                    group.handle.create_workspace(name);
+                    workspace_manager.commit();
+                    self.pending_new_workspaces.push(PendingNewWorkspace {
+                        toplevel: toplevel_handle,
+                        output,
+                        existing: self.sent_workspaces.clone(),
+                    });
                 }
             }
             // TODO version check
             Cmd::MoveWorkspaceBefore(workspace_handle, other_workspace_handle) => {
                 if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get()
-                    && let Some(cosmic_workspace) = self
-                        .workspace_state
-                        .workspaces()
-                        .find(|w| w.handle == workspace_handle)
-                        .and_then(|w| w.cosmic_handle.as_ref())
+                    && let Some(other_workspace_handle) = other_workspace_handle.wayland()
+                    && let Some(cosmic_workspace) = self.cosmic_workspace(&workspace_handle)
                     && cosmic_workspace.version()
                         >= zcosmic_workspace_handle_v2::REQ_MOVE_BEFORE_SINCE
                 {
-                    cosmic_workspace.move_before(&other_workspace_handle, 0);
+                    cosmic_workspace.move_before(other_workspace_handle, 0);
                     workspace_manager.commit();
                 }
             }
             Cmd::MoveWorkspaceAfter(workspace_handle, other_workspace_handle) => {
                 if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get()
-                    && let Some(cosmic_workspace) = self
-                        .workspace_state
-                        .workspaces()
-                        .find(|w| w.handle == workspace_handle)
-                        .and_then(|w| w.cosmic_handle.as_ref())
+                    && let Some(other_workspace_handle) = other_workspace_handle.wayland()
+                    && let Some(cosmic_workspace) = self.cosmic_workspace(&workspace_handle)
                     && cosmic_workspace.version()
                         >= zcosmic_workspace_handle_v2::REQ_MOVE_AFTER_SINCE
                 {
-                    cosmic_workspace.move_after(&other_workspace_handle, 0);
+                    cosmic_workspace.move_after(other_workspace_handle, 0);
+                    workspace_manager.commit();
+                }
+            }
+            Cmd::MoveWorkspaceToOutput(workspace_handle, output, other_workspace_handle) => {
+                if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get()
+                    && let Some(workspace) = workspace_handle
+                        .wayland()
+                        .and_then(|h| self.workspace_state.workspace_info(h))
+                    && workspace
+                        .capabilities
+                        .contains(ext_workspace_handle_v1::WorkspaceCapabilities::Assign)
+                    && let Some(group) = self
+                        .workspace_state
+                        .workspace_groups()
+                        .find(|g| g.outputs.contains(&output))
+                {
+                    workspace.handle.assign(&group.handle);
+                    // Position within the new group, applied in the same commit
+                    if let Some(other_workspace_handle) =
+                        other_workspace_handle.as_ref().and_then(|h| h.wayland())
+                        && let Some(cosmic_workspace) = self.cosmic_workspace(&workspace_handle)
+                        && cosmic_workspace.version()
+                            >= zcosmic_workspace_handle_v2::REQ_MOVE_BEFORE_SINCE
+                    {
+                        cosmic_workspace.move_before(other_workspace_handle, 0);
+                    }
                     workspace_manager.commit();
                 }
             }
             Cmd::ActivateWorkspace(workspace_handle) => {
-                if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get() {
+                if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get()
+                    && let Some(workspace_handle) = workspace_handle.wayland()
+                {
                     workspace_handle.activate();
                     workspace_manager.commit();
                 }
             }
             Cmd::SetWorkspacePinned(workspace_handle, pinned) => {
                 if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get()
-                    && let Some(cosmic_workspace) = self
-                        .workspace_state
-                        .workspaces()
-                        .find(|w| w.handle == workspace_handle)
-                        .and_then(|w| w.cosmic_handle.as_ref())
+                    && let Some(cosmic_workspace) = self.cosmic_workspace(&workspace_handle)
                     && cosmic_workspace.version() >= zcosmic_workspace_handle_v2::REQ_PIN_SINCE
                 {
                     // TODO check capability
@@ -173,9 +278,68 @@ impl AppData {
                     workspace_manager.commit();
                 }
             }
+            Cmd::RenameWorkspace(workspace_handle, name) => {
+                if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get()
+                    && let Some(cosmic_workspace) = self.cosmic_workspace(&workspace_handle)
+                    && cosmic_workspace.version() >= zcosmic_workspace_handle_v2::REQ_RENAME_SINCE
+                {
+                    cosmic_workspace.rename(name);
+                    workspace_manager.commit();
+                }
+            }
+            Cmd::CreateWorkspace(output, name) => {
+                if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get()
+                    && let Some(group) = self
+                        .workspace_state
+                        .workspace_groups()
+                        .find(|g| g.outputs.contains(&output))
+                    && group
+                        .capabilities
+                        .contains(ext_workspace_group_handle_v1::GroupCapabilities::CreateWorkspace)
+                {
+                    group.handle.create_workspace(name);
+                    workspace_manager.commit();
+                }
+            }
+            Cmd::RemoveWorkspace(workspace_handle) => {
+                if let Ok(workspace_manager) = self.workspace_state.workspace_manager().get()
+                    && let Some(workspace) = workspace_handle
+                        .wayland()
+                        .and_then(|h| self.workspace_state.workspace_info(h))
+                    && workspace
+                        .capabilities
+                        .contains(ext_workspace_handle_v1::WorkspaceCapabilities::Remove)
+                {
+                    workspace.handle.remove();
+                    workspace_manager.commit();
+                }
+            }
         }
     }
 
+    fn cosmic_toplevel(
+        &self,
+        handle: &ToplevelHandle,
+    ) -> Option<&zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1> {
+        let handle = handle.wayland()?;
+        self.toplevel_info_state
+            .info(handle)?
+            .cosmic_toplevel
+            .as_ref()
+    }
+
+    fn cosmic_workspace(
+        &self,
+        handle: &WorkspaceHandle,
+    ) -> Option<&zcosmic_workspace_handle_v2::ZcosmicWorkspaceHandleV2> {
+        let handle = handle.wayland()?;
+        self.workspace_state
+            .workspaces()
+            .find(|w| &w.handle == handle)?
+            .cosmic_handle
+            .as_ref()
+    }
+
     fn matches_capture_filter(&self, source: &CaptureSource) -> bool {
         match source {
             CaptureSource::Toplevel(toplevel) => {
@@ -183,11 +347,14 @@ impl AppData {
                     .toplevel_info_state
                     .toplevels()
                     .find(|info| info.foreign_toplevel == *toplevel);
-                if let Some(info) = info {
-                    self.capture_filter.toplevel_matches(info)
-                } else {
-                    false
-                }
+                info.is_some_and(|info| {
+                    info.workspace.iter().any(|workspace| {
+                        self.capture_filter
+                            .toplevels_on_workspaces
+                            .iter()
+                            .any(|w| w.wayland() == Some(workspace))
+                    })
+                })
             }
             CaptureSource::Workspace(workspace) => self
                 .workspace_state
@@ -201,11 +368,75 @@ impl AppData {
         }
     }
 
+    fn capture_rate(&self, source: &CaptureSource) -> CaptureRate {
+        let rates = &self.capture_filter.rates;
+        match source {
+            CaptureSource::Toplevel(toplevel) => {
+                if self
+                    .capture_filter
+                    .active_toplevels
+                    .iter()
+                    .any(|t| t.wayland() == Some(toplevel))
+                {
+                    rates.active_toplevel
+                } else {
+                    rates.toplevel
+                }
+            }
+            CaptureSource::Workspace(_) => rates.workspace,
+            CaptureSource::Output(_) => CaptureRate::Full,
+        }
+    }
+
+    fn preview_size(&self, source: &CaptureSource) -> Option<(u32, u32)> {
+        match source {
+            CaptureSource::Toplevel(toplevel) => self
+                .capture_filter
+                .toplevel_sizes
+                .iter()
+                .find(|(t, _)| t.wayland() == Some(toplevel))
+                .map(|(_, size)| *size),
+            CaptureSource::Workspace(_) => self.capture_filter.workspace_size,
+            CaptureSource::Output(_) => None,
+        }
+    }
+
+    /// Factor to scale a capture of `source` down by, while still being at least
+    /// the size of the preview
+    fn downscale_factor(
+        &self,
+        source: &CaptureSource,
+        (width, height): (u32, u32),
+        transform: wl_output::Transform,
+    ) -> u32 {
+        let Some((mut preview_width, mut preview_height)) = self.preview_size(source) else {
+            return 1;
+        };
+        if matches!(
+            transform,
+            wl_output::Transform::_90
+                | wl_output::Transform::_270
+                | wl_output::Transform::Flipped90
+                | wl_output::Transform::Flipped270
+        ) {
+            (preview_width, preview_height) = (preview_height, preview_width);
+        }
+        (width / preview_width.max(1))
+            .min(height / preview_height.max(1))
+            .max(1)
+    }
+
     fn invalidate_capture_filter(&self) {
         for (source, capture) in self.captures.borrow_mut().iter_mut() {
             let matches = self.matches_capture_filter(source);
             if matches {
                 capture.start(&self.screencopy_state, &self.qh);
+                // Resume capturing if no longer limited to a snapshot
+                if let Some(session) = capture.session.lock().unwrap().as_mut()
+                    && session.paused
+                {
+                    self.schedule_capture(capture, session);
+                }
             } else {
                 capture.stop();
             }
@@ -289,6 +520,7 @@ fn start(conn: Connection) -> mpsc::Receiver<Event> {
         // TODO: The `calloop` executor doesn't seem to be working properly, so
         // spawn an executor using one additional thread.
         let thread_pool = ThreadPool::builder().pool_size(1).create().unwrap();
+        let downscale_pool = ThreadPool::builder().pool_size(1).create().unwrap();
 
         let vulkan = vulkan::Vulkan::new();
         if let Err(err) = &vulkan {
@@ -298,9 +530,21 @@ fn start(conn: Connection) -> mpsc::Receiver<Event> {
             );
         }
 
+        let quirk_rules = match cosmic_config::Config::new(crate::APP_ID, crate::CONFIG_VERSION) {
+            Ok(config) => quirks::config_rules(&config),
+            Err(err) => {
+                log::error!("Failed to open config for GPU quirks: {}", err);
+                Vec::new()
+            }
+        };
+
+        let mut event_loop = calloop::EventLoop::try_new().unwrap();
+
         let registry_state = RegistryState::new(&globals);
         let mut app_data = AppData {
+            conn: conn.clone(),
             qh: qh.clone(),
+            loop_handle: event_loop.handle(),
             dmabuf_state,
             workspace_state: WorkspaceState::new(&registry_state, &qh), // Create before toplevel info state
             toplevel_info_state: ToplevelInfoState::new(&registry_state, &qh),
@@ -315,13 +559,20 @@ fn start(conn: Connection) -> mpsc::Receiver<Event> {
             dmabuf_feedback: None,
             gbm_devices: GbmDevices::default(),
             thread_pool,
+            downscale_pool,
             vulkan: vulkan.ok(),
+            quirks: quirks::QuirkTable::new(quirk_rules),
+            pending_new_workspaces: Vec::new(),
+            sent_workspaces: HashSet::new(),
         };
 
+        if let Err(err) = app_data.watch_drm_devices() {
+            log::warn!("Failed to watch for DRM device changes: {}", err);
+        }
+
         let (cmd_sender, cmd_channel) = calloop::channel::channel();
         app_data.send_event(Event::CmdSender(cmd_sender));
 
-        let mut event_loop = calloop::EventLoop::try_new().unwrap();
         WaylandSource::new(conn, event_queue)
             .insert(event_loop.handle())
             .unwrap();
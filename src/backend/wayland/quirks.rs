@@ -0,0 +1,283 @@
+//! Workarounds for GPUs or drivers with broken buffer sharing, matched by PCI
+//! vendor and device ID, Vulkan device name, or Vulkan driver name.
+//!
+//! Rules in the `gpu_quirks` config key are checked before the built-in rules,
+//! and the first matching rule is used, so a rule with no quirks can disable a
+//! built-in workaround. Read once on startup. For example:
+//!
+//! ```ron
+//! [
+//!     (vendor_id: Some(0x8086), device_ids: [0x7d45], quirks: []),
+//!     (vendor_id: Some(0x8086), name_contains: Some("(MTL)"), quirks: []),
+//!     (driver: Some("NVIDIA"), quirks: [ForceLinear]),
+//! ]
+//! ```
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::vulkan::{DeviceInfo, Vulkan};
+
+const CONFIG_KEY: &str = "gpu_quirks";
+
+const INTEL: u32 = 0x8086;
+
+// Meteor Lake, Arrow Lake, and Lunar Lake, from `INTEL_MTL_IDS`, `INTEL_ARL_IDS`,
+// and `INTEL_LNL_IDS` in Linux's `include/drm/intel/pciids.h`
+const INTEL_MTL_ARL_LNL: &[u32] = &[
+    0x7d40, 0x7d45, 0x7d55, 0x7d60, 0x7dd5, // MTL
+    0x7d41, 0x7d51, 0x7d67, 0x7dd1, 0xb640, // ARL
+    0x6420, 0x64a0, 0x64b0, // LNL
+];
+
+// Suffix of the Vulkan device name Mesa gives these, for devices added after
+// the list above
+const INTEL_MTL_ARL_LNL_NAMES: &[&str] = &["(MTL)", "(ARL)", "(LNL)"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum Quirk {
+    /// Capture to shm buffers from this device
+    ForceShm,
+    /// Only allocate capture buffers with the linear modifier
+    ForceLinear,
+    /// Don't use dmabufs if this is the capture device, or the main device
+    /// previews are shown with
+    DisableDmabuf,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct QuirkRule {
+    pub vendor_id: Option<u32>,
+    /// Matches any device of the vendor if empty
+    pub device_ids: Vec<u32>,
+    /// Substring of the Vulkan device name, as shown by `vulkaninfo`
+    pub name_contains: Option<String>,
+    /// Vulkan driver name, as shown by `vulkaninfo`
+    pub driver: Option<String>,
+    pub quirks: Vec<Quirk>,
+}
+
+impl QuirkRule {
+    fn matches(&self, info: &DeviceInfo) -> bool {
+        self.vendor_id.is_none_or(|id| id == info.vendor_id)
+            && (self.device_ids.is_empty() || self.device_ids.contains(&info.device_id))
+            && self
+                .name_contains
+                .as_ref()
+                .is_none_or(|name| info.name.contains(name.as_str()))
+            && self
+                .driver
+                .as_ref()
+                .is_none_or(|driver| info.driver_name.as_ref() == Some(driver))
+    }
+}
+
+fn builtin_rules() -> Vec<QuirkRule> {
+    // TODO: Capturing to dmabufs shows corrupted previews
+    let mut rules = vec![QuirkRule {
+        vendor_id: Some(INTEL),
+        device_ids: INTEL_MTL_ARL_LNL.to_vec(),
+        quirks: vec![Quirk::ForceShm],
+        ..Default::default()
+    }];
+    rules.extend(INTEL_MTL_ARL_LNL_NAMES.iter().map(|name| QuirkRule {
+        vendor_id: Some(INTEL),
+        name_contains: Some(name.to_string()),
+        quirks: vec![Quirk::ForceShm],
+        ..Default::default()
+    }));
+    rules
+}
+
+/// Rules set in the `gpu_quirks` key of `config`
+pub fn config_rules(config: &cosmic_config::Config) -> Vec<QuirkRule> {
+    match config.get::<Vec<QuirkRule>>(CONFIG_KEY) {
+        Ok(rules) => rules,
+        Err(err) => {
+            if err.is_err() {
+                log::error!("Failed to load GPU quirks: {}", err);
+            }
+            Vec::new()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    pub force_shm: bool,
+    pub force_linear: bool,
+    pub disable_dmabuf: bool,
+}
+
+impl Quirks {
+    fn from_list(list: &[Quirk]) -> Self {
+        Self {
+            force_shm: list.contains(&Quirk::ForceShm),
+            force_linear: list.contains(&Quirk::ForceLinear),
+            disable_dmabuf: list.contains(&Quirk::DisableDmabuf),
+        }
+    }
+}
+
+pub struct QuirkTable {
+    rules: Vec<QuirkRule>,
+    cache: HashMap<u64, Quirks>,
+}
+
+impl QuirkTable {
+    /// `config_rules` followed by built-in rules
+    pub fn new(config_rules: Vec<QuirkRule>) -> Self {
+        let mut rules = config_rules;
+        rules.extend(builtin_rules());
+        Self {
+            rules,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// First rule matching `info`
+    fn rule(&self, info: &DeviceInfo) -> Option<&QuirkRule> {
+        self.rules.iter().find(|rule| rule.matches(info))
+    }
+
+    /// Quirks for the DRM device `dev`, or none if it can't be identified
+    pub fn device_quirks(&mut self, vulkan: Option<&mut Vulkan>, dev: u64) -> Quirks {
+        if let Some(quirks) = self.cache.get(&dev) {
+            return *quirks;
+        }
+        let quirks = match vulkan.map(|vulkan| vulkan.device_info(dev)) {
+            Some(Ok(Some(info))) => {
+                let rule = self.rule(info);
+                if let Some(rule) = rule
+                    && !rule.quirks.is_empty()
+                {
+                    log::info!(
+                        "Using GPU quirks {:?} for '{}' ({:04x}:{:04x})",
+                        rule.quirks,
+                        info.name,
+                        info.vendor_id,
+                        info.device_id
+                    );
+                }
+                rule.map_or_else(Quirks::default, |rule| Quirks::from_list(&rule.quirks))
+            }
+            _ => Quirks::default(),
+        };
+        self.cache.insert(dev, quirks);
+        quirks
+    }
+
+    /// Forget quirks of devices, since device numbers may be reused by a
+    /// different device
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(name: &str, vendor_id: u32, device_id: u32, driver: Option<&str>) -> DeviceInfo {
+        DeviceInfo {
+            name: name.to_string(),
+            vendor_id,
+            device_id,
+            driver_name: driver.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn rule_matches() {
+        let mtl = device(
+            "Intel(R) Graphics (MTL)",
+            INTEL,
+            0x7d55,
+            Some("Intel open-source Mesa driver"),
+        );
+        let unlisted = device("Intel(R) Graphics (LNL)", INTEL, 0x64ff, None);
+        let other = device(
+            "AMD Radeon 780M (RADV PHOENIX)",
+            0x1002,
+            0x15bf,
+            Some("radv"),
+        );
+
+        // Empty rule matches any device
+        assert!(QuirkRule::default().matches(&other));
+
+        let by_id = QuirkRule {
+            vendor_id: Some(INTEL),
+            device_ids: vec![0x7d55],
+            ..Default::default()
+        };
+        assert!(by_id.matches(&mtl));
+        assert!(!by_id.matches(&unlisted));
+        assert!(!by_id.matches(&device("", 0x1002, 0x7d55, None)));
+
+        let by_vendor = QuirkRule {
+            vendor_id: Some(INTEL),
+            ..Default::default()
+        };
+        assert!(by_vendor.matches(&unlisted));
+        assert!(!by_vendor.matches(&other));
+
+        let by_name = QuirkRule {
+            name_contains: Some("(LNL)".to_string()),
+            ..Default::default()
+        };
+        assert!(by_name.matches(&unlisted));
+        assert!(!by_name.matches(&mtl));
+
+        let by_driver = QuirkRule {
+            driver: Some("radv".to_string()),
+            ..Default::default()
+        };
+        assert!(by_driver.matches(&other));
+        assert!(!by_driver.matches(&mtl));
+        // Unknown driver doesn't match
+        assert!(!by_driver.matches(&unlisted));
+    }
+
+    #[test]
+    fn builtin_rules_match_by_id_or_name() {
+        let table = QuirkTable::new(Vec::new());
+        let quirks = |info: DeviceInfo| table.rule(&info).map(|rule| rule.quirks.clone());
+        assert_eq!(
+            quirks(device("", INTEL, 0x7d45, None)),
+            Some(vec![Quirk::ForceShm])
+        );
+        assert_eq!(
+            quirks(device("Intel(R) Graphics (ARL)", INTEL, 0x7dff, None)),
+            Some(vec![Quirk::ForceShm])
+        );
+        assert_eq!(
+            quirks(device("Intel(R) Graphics (TGL GT2)", INTEL, 0x9a49, None)),
+            None
+        );
+    }
+
+    #[test]
+    fn config_rules_before_builtin() {
+        let mtl = device("Intel(R) Graphics (MTL)", INTEL, 0x7d45, None);
+        let table = QuirkTable::new(vec![
+            // Disables the built-in workaround
+            QuirkRule {
+                vendor_id: Some(INTEL),
+                device_ids: vec![0x7d45],
+                ..Default::default()
+            },
+            QuirkRule {
+                vendor_id: Some(INTEL),
+                quirks: vec![Quirk::ForceLinear],
+                ..Default::default()
+            },
+        ]);
+        assert!(table.rule(&mtl).unwrap().quirks.is_empty());
+        assert_eq!(
+            table.rule(&device("", INTEL, 0x7d55, None)).unwrap().quirks,
+            [Quirk::ForceLinear]
+        );
+    }
+}
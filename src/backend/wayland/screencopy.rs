@@ -1,3 +1,4 @@
+use calloop::timer::{TimeoutAction, Timer};
 use cosmic::cctk::screencopy::{
     CaptureFrame, CaptureOptions, CaptureSession, CaptureSource, FailureReason, Formats, Frame,
     ScreencopyFrameData, ScreencopyFrameDataExt, ScreencopyHandler, ScreencopySessionData,
@@ -5,13 +6,17 @@ use cosmic::cctk::screencopy::{
 };
 use cosmic::cctk::wayland_client::{Connection, QueueHandle, WEnum};
 use cosmic::cctk::{self};
+use cosmic::iced::futures::SinkExt;
 use cosmic::iced::platform_specific::shell::subsurface_widget::{
-    SubsurfaceBuffer, SubsurfaceBufferRelease,
+    BufferSource, SubsurfaceBuffer, SubsurfaceBufferRelease,
 };
-use std::array;
 use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
-use super::{AppData, Buffer, Capture, CaptureImage, Event};
+#[cfg(not(feature = "force-shm-screencopy"))]
+use super::blit::Blitter;
+use super::{AppData, Buffer, Capture, CaptureImage, Event, buffer};
+use crate::backend::{CaptureRate, ToplevelHandle, WorkspaceHandle};
 
 // Number of buffers to swap between
 const BUFFER_COUNT: usize = 2;
@@ -20,10 +25,22 @@ pub struct ScreencopySession {
     formats: Option<Formats>,
     // swapchain buffers
     buffers: Option<[Buffer; BUFFER_COUNT]>,
+    // Buffers shown instead of `buffers`, when downscaled to the size of the preview
+    scaled_buffers: Option<[Buffer; BUFFER_COUNT]>,
     session: CaptureSession,
     // Future signaled when buffer is signaled.
     // if triple buffer is used, will need more than one.
     release: Option<SubsurfaceBufferRelease>,
+    // Release of the back buffer, to wait for before capturing into it
+    back_release: Option<SubsurfaceBufferRelease>,
+    // A frame has been requested, and isn't yet ready or failed
+    frame_pending: bool,
+    // Time last frame was ready, for limiting capture rate
+    last_frame: Option<Instant>,
+    // Not capturing again, since capture rate is `CaptureRate::Snapshot`
+    pub paused: bool,
+    // Buffers need to be replaced once the pending frame is done with them
+    reallocate: bool,
 }
 
 impl ScreencopySession {
@@ -45,8 +62,14 @@ impl ScreencopySession {
         Self {
             formats: None,
             buffers: None,
+            scaled_buffers: None,
             session,
             release: None,
+            back_release: None,
+            frame_pending: false,
+            last_frame: None,
+            paused: false,
+            reallocate: false,
         }
     }
 
@@ -56,9 +79,14 @@ impl ScreencopySession {
         conn: &Connection,
         qh: &QueueHandle<AppData>,
     ) {
+        // A delayed capture may race with one after a buffer constraint failure
+        if self.frame_pending {
+            return;
+        }
         let Some(back) = self.buffers.as_ref().map(|x| &x[1]) else {
             return;
         };
+        self.frame_pending = true;
 
         // TODO
         // let node = back.node().and_then(|x| x.to_str().map(|x| x.to_string()));
@@ -100,6 +128,233 @@ impl ScreencopyFrameDataExt for FrameData {
     }
 }
 
+impl AppData {
+    fn create_buffers(&mut self, formats: &Formats) -> Option<[Buffer; BUFFER_COUNT]> {
+        let buffers = (0..BUFFER_COUNT)
+            .map(|_| self.create_buffer(formats))
+            .collect::<Option<Vec<_>>>()?;
+        buffers.try_into().ok()
+    }
+
+    /// Replace buffers of every capture session, since the devices they may be
+    /// allocated on have changed
+    pub(super) fn reallocate_capture_buffers(&mut self) {
+        let captures = self.captures.borrow().values().cloned().collect::<Vec<_>>();
+        for capture in captures {
+            let mut session = capture.session.lock().unwrap();
+            let Some(session) = session.as_mut() else {
+                continue;
+            };
+            if session.frame_pending {
+                // Server may still be writing to the back buffer
+                session.reallocate = true;
+            } else {
+                self.reallocate_buffers(&capture, session);
+            }
+        }
+    }
+
+    /// Create new buffers for the session, then trigger another capture
+    fn reallocate_buffers(&mut self, capture: &Arc<Capture>, session: &mut ScreencopySession) {
+        session.reallocate = false;
+        if let Some(formats) = &session.formats {
+            session.buffers = self.create_buffers(formats);
+        }
+        session.scaled_buffers = None;
+        session.back_release = None;
+        session.attach_buffer_and_commit(capture, &self.conn, &self.qh);
+    }
+
+    /// Capture the next frame, once the back buffer is released and no sooner
+    /// than the source's `CaptureRate` allows
+    pub(super) fn schedule_capture(&self, capture: &Arc<Capture>, session: &mut ScreencopySession) {
+        let Some(interval) = self.capture_rate(&capture.source).interval() else {
+            // Resumed by `invalidate_capture_filter` if the rate changes
+            session.paused = true;
+            return;
+        };
+        session.paused = false;
+        let delay = session.last_frame.map_or(Duration::ZERO, |last_frame| {
+            (last_frame + interval).saturating_duration_since(Instant::now())
+        });
+
+        let capture = capture.clone();
+        let conn = self.conn.clone();
+        let qh = self.qh.clone();
+        let release = session.back_release.take();
+        let thread_pool = self.thread_pool.clone();
+        let capture_again = move || {
+            thread_pool.spawn_ok(async move {
+                if let Some(release) = release {
+                    // Wait for buffer to be released by server
+                    release.await;
+                }
+                let mut session = capture.session.lock().unwrap();
+                let Some(session) = session.as_mut() else {
+                    return;
+                };
+                session.attach_buffer_and_commit(&capture, &conn, &qh);
+            });
+        };
+        if delay.is_zero() {
+            capture_again();
+        } else {
+            let mut capture_again = Some(capture_again);
+            let res =
+                self.loop_handle
+                    .insert_source(Timer::from_duration(delay), move |_, _, _| {
+                        if let Some(capture_again) = capture_again.take() {
+                            capture_again();
+                        }
+                        TimeoutAction::Drop
+                    });
+            if let Err(err) = res {
+                log::error!("Failed to schedule capture: {}", err);
+            }
+        }
+    }
+}
+
+/// Preview a capture is shown in
+enum CaptureTarget {
+    Toplevel(ToplevelHandle),
+    Workspace(WorkspaceHandle),
+}
+
+impl CaptureTarget {
+    fn event(self, image: CaptureImage) -> Event {
+        match self {
+            Self::Toplevel(handle) => Event::ToplevelCapture(handle, image),
+            Self::Workspace(handle) => Event::WorkspaceCapture(handle, image),
+        }
+    }
+}
+
+/// Way a capture is scaled down to the size of its preview
+enum Downscaler {
+    /// Blit dmabufs on the GPU
+    #[cfg(not(feature = "force-shm-screencopy"))]
+    Gpu(Arc<Blitter>),
+    /// Average pixels of shm buffers on the CPU
+    Cpu,
+}
+
+impl Downscaler {
+    /// Start scaling `src` down by `factor` to `dst`, returning work to finish
+    /// off the event thread, or `None` if it can't be scaled
+    fn start(&self, src: &Buffer, dst: &Buffer, factor: u32) -> Option<Box<dyn FnOnce() + Send>> {
+        match self {
+            #[cfg(not(feature = "force-shm-screencopy"))]
+            Self::Gpu(blitter) => {
+                let (BufferSource::Dma(src), BufferSource::Dma(dst)) =
+                    (&*src.backing, &*dst.backing)
+                else {
+                    return None;
+                };
+                match blitter.blit(src, dst, factor) {
+                    Ok(pending) => Some(Box::new(move || {
+                        if let Err(err) = pending.submit_and_wait() {
+                            log::error!("Failed to run downscaling blit: {}", err);
+                        }
+                    })),
+                    Err(err) => {
+                        log::error!("Failed to downscale capture: {}", err);
+                        None
+                    }
+                }
+            }
+            Self::Cpu => Some(Box::new(buffer::downscale_job(src, dst, factor)?)),
+        }
+    }
+}
+
+impl AppData {
+    fn capture_target(&self, source: &CaptureSource) -> Option<CaptureTarget> {
+        match source {
+            CaptureSource::Toplevel(toplevel) => self
+                .toplevel_info_state
+                .toplevels()
+                .find(|info| info.foreign_toplevel == *toplevel)
+                .map(|info| {
+                    CaptureTarget::Toplevel(ToplevelHandle::Wayland(info.foreign_toplevel.clone()))
+                }),
+            CaptureSource::Workspace(workspace) => Some(CaptureTarget::Workspace(
+                WorkspaceHandle::Wayland(workspace.clone()),
+            )),
+            CaptureSource::Output(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Way to scale `front` down, if supported
+    ///
+    /// Dmabufs are scaled on the GPU if the driver can import them. Shm buffers
+    /// are scaled on the CPU, off the event thread, unless captured at the full
+    /// rate, where that would cost more than showing them at full size.
+    fn downscaler(&mut self, source: &CaptureSource, front: &Buffer) -> Option<Downscaler> {
+        match &*front.backing {
+            #[cfg(not(feature = "force-shm-screencopy"))]
+            BufferSource::Dma(dmabuf) => {
+                let blitter = self.vulkan.as_mut()?.blitter(front.dev?)?;
+                blitter
+                    .can_blit_from(dmabuf)
+                    .then_some(Downscaler::Gpu(blitter))
+            }
+            BufferSource::Shm(_)
+                if front.mmap.is_some()
+                    && buffer::is_8bpc(front.format)
+                    && self.capture_rate(source) != CaptureRate::Full =>
+            {
+                Some(Downscaler::Cpu)
+            }
+            _ => None,
+        }
+    }
+
+    fn create_scaled_buffers(
+        &mut self,
+        formats: &Formats,
+        downscaler: &Downscaler,
+        front: &Buffer,
+        size: (u32, u32),
+    ) -> Option<[Buffer; BUFFER_COUNT]> {
+        let buffers = (0..BUFFER_COUNT)
+            .map(|_| match downscaler {
+                #[cfg(not(feature = "force-shm-screencopy"))]
+                Downscaler::Gpu(blitter) => {
+                    let BufferSource::Dma(dmabuf) = &*front.backing else {
+                        return None;
+                    };
+                    // Modifiers the compositor can also show the buffer with
+                    let dst_modifiers = blitter.dst_modifiers(dmabuf.format);
+                    let modifiers = formats
+                        .dmabuf_formats
+                        .iter()
+                        .find(|(format, _)| *format == dmabuf.format)
+                        .map(|(_, modifiers)| {
+                            modifiers
+                                .iter()
+                                .copied()
+                                .filter(|modifier| dst_modifiers.contains(modifier))
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default();
+                    match self.create_gbm_buffer(front.format, &modifiers, size, false, front.dev) {
+                        Ok(buffer) => buffer,
+                        Err(err) => {
+                            log::error!("Failed to create gbm buffer for downscaling: {}", err);
+                            None
+                        }
+                    }
+                }
+                Downscaler::Cpu => Some(self.create_shm_buffer(front.format, size)),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        buffers.try_into().ok()
+    }
+}
+
 impl ScreencopyHandler for AppData {
     fn screencopy_state(&mut self) -> &mut ScreencopyState {
         &mut self.screencopy_state
@@ -124,15 +379,15 @@ impl ScreencopyHandler for AppData {
 
         // Create new buffer if none, then start capturing
         if session.buffers.is_none() {
-            session.buffers = Some(array::from_fn(|_| self.create_buffer(formats)));
+            session.buffers = self.create_buffers(formats);
             session.attach_buffer_and_commit(&capture, conn, &self.qh);
         }
     }
 
     fn ready(
         &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
         capture_frame: &CaptureFrame,
         frame: Frame,
     ) {
@@ -150,25 +405,24 @@ impl ScreencopyHandler for AppData {
             return;
         }
 
+        session.frame_pending = false;
+        session.last_frame = Some(Instant::now());
+
+        if session.reallocate {
+            // Frame is in a buffer on a device that may no longer exist
+            self.reallocate_buffers(&capture, session);
+            return;
+        }
+
         // swap buffers
         session.buffers.as_mut().unwrap().rotate_left(1);
+        if let Some(scaled_buffers) = &mut session.scaled_buffers {
+            scaled_buffers.rotate_left(1);
+        }
+        session.back_release = session.release.take();
 
         // Capture again on damage
-        let capture_clone = capture.clone();
-        let conn = conn.clone();
-        let release = session.release.take();
-        let qh = qh.clone();
-        self.thread_pool.spawn_ok(async move {
-            if let Some(release) = release {
-                // Wait for buffer to be released by server
-                release.await;
-            }
-            let mut session = capture_clone.session.lock().unwrap();
-            let Some(session) = session.as_mut() else {
-                return;
-            };
-            session.attach_buffer_and_commit(&capture_clone, &conn, &qh);
-        });
+        self.schedule_capture(&capture, session);
 
         // Clear `buffer_damage` for front buffer; accumulate for other buffers.
         session.buffers.as_mut().unwrap()[0].buffer_damage.clear();
@@ -176,46 +430,87 @@ impl ScreencopyHandler for AppData {
             buffer.buffer_damage.extend_from_slice(&frame.damage);
         }
 
+        let transform = match frame.transform {
+            WEnum::Value(value) => value,
+            WEnum::Unknown(value) => panic!("invalid capture transform: {}", value),
+        };
+
+        // The capture protocol has no way to request a smaller frame, so scale
+        // captures much larger than the preview down before showing them.
         let front = &session.buffers.as_ref().unwrap()[0];
-        let (buffer, release) = SubsurfaceBuffer::new(front.backing.clone());
-        session.release = Some(release);
-        let image = CaptureImage {
-            wl_buffer: buffer,
-            width: front.size.0,
-            height: front.size.1,
-            transform: match frame.transform {
-                WEnum::Value(value) => value,
-                WEnum::Unknown(value) => panic!("invalid capture transform: {}", value),
-            },
-            #[cfg(feature = "no-subsurfaces")]
-            image: cosmic::widget::image::Handle::from_rgba(
-                front.size.0,
-                front.size.1,
-                front.mmap.to_vec(),
-            ),
+        let factor = self.downscale_factor(&capture.source, front.size, transform);
+        let downscaler = if factor > 1 {
+            self.downscaler(&capture.source, front)
+        } else {
+            None
         };
-        match &capture.source {
-            CaptureSource::Toplevel(toplevel) => {
-                let info = self
-                    .toplevel_info_state
-                    .toplevels()
-                    .find(|info| info.foreign_toplevel == *toplevel);
-                if let Some(info) = info {
-                    self.send_event(Event::ToplevelCapture(info.foreign_toplevel.clone(), image))
-                }
+        if let Some(downscaler) = &downscaler {
+            let size = (front.size.0 / factor, front.size.1 / factor);
+            if session.scaled_buffers.as_ref().is_none_or(|buffers| {
+                buffers[0].size != size
+                    || buffers[0].format != front.format
+                    || buffers[0].is_dmabuf() != front.is_dmabuf()
+            }) {
+                session.scaled_buffers = session.formats.as_ref().and_then(|formats| {
+                    self.create_scaled_buffers(formats, downscaler, front, size)
+                });
             }
-            CaptureSource::Workspace(workspace) => {
-                self.send_event(Event::WorkspaceCapture(workspace.clone(), image));
-            }
-            CaptureSource::Output(_) => {
-                unreachable!()
+        } else {
+            session.scaled_buffers = None;
+        }
+
+        let mut front = &session.buffers.as_ref().unwrap()[0];
+        let mut job = None;
+        if let Some(downscaler) = &downscaler
+            && let Some(scaled_buffers) = &session.scaled_buffers
+        {
+            job = downscaler.start(front, &scaled_buffers[0], factor);
+            if job.is_some() {
+                front = &scaled_buffers[0];
             }
+        }
+
+        let (wl_buffer, release) = SubsurfaceBuffer::new(front.backing.clone());
+        session.release = Some(release);
+        let Some(target) = self.capture_target(&capture.source) else {
+            return;
+        };
+        let (width, height) = front.size;
+        let bytes_per_pixel = buffer::bytes_per_pixel(front.format);
+        #[cfg(feature = "no-subsurfaces")]
+        let mmap = front.mmap.clone();
+        let event = move || {
+            target.event(CaptureImage {
+                wl_buffer,
+                width,
+                height,
+                bytes_per_pixel,
+                transform,
+                #[cfg(feature = "no-subsurfaces")]
+                image: cosmic::widget::image::Handle::from_rgba(
+                    width,
+                    height,
+                    mmap.as_ref()
+                        .map_or_else(Vec::new, |mmap| unsafe { buffer::mapped(mmap) }.to_vec()),
+                ),
+            })
         };
+        if let Some(job) = job {
+            // The image holds the scaled buffer until shown, so its release, and the
+            // release the source buffer is captured into again after, wait for the job.
+            let mut sender = self.sender.clone();
+            self.downscale_pool.spawn_ok(async move {
+                job();
+                let _ = sender.send(event()).await;
+            });
+        } else {
+            self.send_event(event());
+        }
     }
 
     fn failed(
         &mut self,
-        conn: &Connection,
+        _conn: &Connection,
         _qh: &QueueHandle<Self>,
         capture_frame: &CaptureFrame,
         reason: WEnum<FailureReason>,
@@ -224,6 +519,9 @@ impl ScreencopyHandler for AppData {
         let Some(capture) = capture.upgrade() else {
             return;
         };
+        if let Some(session) = capture.session.lock().unwrap().as_mut() {
+            session.frame_pending = false;
+        }
         if reason == WEnum::Value(FailureReason::BufferConstraints) {
             // Re-allocate buffers, then trigger another capture
             log::info!("buffer constraint failure; re-allocating");
@@ -231,10 +529,7 @@ impl ScreencopyHandler for AppData {
             let Some(session) = session.as_mut() else {
                 return;
             };
-            if let Some(formats) = &session.formats {
-                session.buffers = Some(array::from_fn(|_| self.create_buffer(formats)));
-            }
-            session.attach_buffer_and_commit(&capture, conn, &self.qh);
+            self.reallocate_buffers(&capture, session);
         } else {
             // TODO
             if reason == WEnum::Value(FailureReason::Stopped) {
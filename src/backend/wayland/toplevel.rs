@@ -6,6 +6,35 @@ use cosmic::cctk;
 use wayland_protocols::ext::foreign_toplevel_list::v1::client::ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1;
 
 use super::{AppData, CaptureSource, Event};
+use crate::backend::{ToplevelGeometry, ToplevelHandle, ToplevelInfo, WorkspaceHandle};
+
+fn toplevel_info(info: &cctk::toplevel_info::ToplevelInfo) -> ToplevelInfo {
+    ToplevelInfo {
+        title: info.title.clone(),
+        app_id: info.app_id.clone(),
+        state: info.state.clone(),
+        output: info.output.clone(),
+        workspace: info
+            .workspace
+            .iter()
+            .cloned()
+            .map(WorkspaceHandle::Wayland)
+            .collect(),
+        geometry: info
+            .geometry
+            .iter()
+            .map(|(output, geometry)| {
+                let geometry = ToplevelGeometry {
+                    x: geometry.x,
+                    y: geometry.y,
+                    width: geometry.width,
+                    height: geometry.height,
+                };
+                (output.clone(), geometry)
+            })
+            .collect(),
+    }
+}
 
 // TODO any indication when we have all toplevels?
 impl ToplevelInfoHandler for AppData {
@@ -20,7 +49,12 @@ impl ToplevelInfoHandler for AppData {
         toplevel: &ExtForeignToplevelHandleV1,
     ) {
         let info = self.toplevel_info_state.info(toplevel).unwrap();
-        self.send_event(Event::NewToplevel(toplevel.clone(), info.clone()));
+        let info = toplevel_info(info);
+        self.send_event(Event::NewToplevel(
+            ToplevelHandle::Wayland(toplevel.clone()),
+            info,
+            false,
+        ));
 
         self.add_capture_source(CaptureSource::Toplevel(toplevel.clone()));
     }
@@ -32,7 +66,11 @@ impl ToplevelInfoHandler for AppData {
         toplevel: &ExtForeignToplevelHandleV1,
     ) {
         let info = self.toplevel_info_state.info(toplevel).unwrap();
-        self.send_event(Event::UpdateToplevel(toplevel.clone(), info.clone()));
+        let info = toplevel_info(info);
+        self.send_event(Event::UpdateToplevel(
+            ToplevelHandle::Wayland(toplevel.clone()),
+            info,
+        ));
     }
 
     fn toplevel_closed(
@@ -41,7 +79,9 @@ impl ToplevelInfoHandler for AppData {
         _qh: &QueueHandle<Self>,
         toplevel: &ExtForeignToplevelHandleV1,
     ) {
-        self.send_event(Event::CloseToplevel(toplevel.clone()));
+        self.send_event(Event::CloseToplevel(ToplevelHandle::Wayland(
+            toplevel.clone(),
+        )));
 
         self.remove_capture_source(CaptureSource::Toplevel(toplevel.clone()));
     }
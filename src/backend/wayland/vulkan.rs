@@ -2,12 +2,37 @@ use ash::prelude::VkResult;
 use ash::vk;
 use std::collections::HashMap;
 use std::ffi::CStr;
+use std::sync::Arc;
 
-pub struct Vulkan {
-    instance: ash::Instance,
+use super::blit::Blitter;
+
+/// Identification of a physical device, for matching GPU quirks
+#[derive(Debug)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    /// `None` if `VK_KHR_driver_properties` isn't supported
+    pub driver_name: Option<String>,
+}
+
+/// Instance shared with each `Blitter`, so it is destroyed after them
+pub struct Instance {
+    pub instance: ash::Instance,
     _entry: ash::Entry,
-    // TODO purge cache at some point
-    device_name_cache: HashMap<u64, VkResult<Option<String>>>,
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe { self.instance.destroy_instance(None) };
+    }
+}
+
+pub struct Vulkan {
+    instance: Arc<Instance>,
+    // Recreated with the instance when DRM devices change
+    device_info_cache: HashMap<u64, VkResult<Option<DeviceInfo>>>,
+    blitters: HashMap<u64, Option<Arc<Blitter>>>,
 }
 
 impl Vulkan {
@@ -23,64 +48,116 @@ impl Vulkan {
         };
         let instance = unsafe { entry.create_instance(&create_info, None)? };
         Ok(Self {
-            _entry: entry,
-            instance,
-            device_name_cache: HashMap::new(),
+            instance: Arc::new(Instance {
+                instance,
+                _entry: entry,
+            }),
+            device_info_cache: HashMap::new(),
+            blitters: HashMap::new(),
         })
     }
 
-    pub fn device_name(&mut self, dev: u64) -> VkResult<Option<&str>> {
-        if !self.device_name_cache.contains_key(&dev) {
-            let value = self.device_name_uncached(dev);
+    pub fn device_info(&mut self, dev: u64) -> VkResult<Option<&DeviceInfo>> {
+        if !self.device_info_cache.contains_key(&dev) {
+            let value = self.device_info_uncached(dev);
             if let Err(err) = &value {
                 log::error!("Failed to query Vulkan device properties: {}", err);
             }
-            self.device_name_cache.insert(dev, value);
+            self.device_info_cache.insert(dev, value);
         }
-        self.device_name_cache
+        self.device_info_cache
             .get(&dev)
             .unwrap()
             .as_ref()
-            .map(|x| x.as_deref())
+            .map(|x| x.as_ref())
             .map_err(|err| *err)
     }
 
-    fn device_name_uncached(&mut self, dev: u64) -> VkResult<Option<String>> {
-        let devices = unsafe { self.instance.enumerate_physical_devices()? };
+    /// Blitter for scaling dmabufs on the DRM device `dev` down, or `None` if the
+    /// driver doesn't support importing dmabufs
+    pub fn blitter(&mut self, dev: u64) -> Option<Arc<Blitter>> {
+        if !self.blitters.contains_key(&dev) {
+            let blitter = match self.physical_device(dev) {
+                Ok(Some(device)) => match Blitter::new(self.instance.clone(), device) {
+                    Ok(blitter) => blitter.map(Arc::new),
+                    Err(err) => {
+                        log::error!("Failed to create Vulkan device for downscaling: {}", err);
+                        None
+                    }
+                },
+                Ok(None) => None,
+                Err(err) => {
+                    log::error!("Failed to enumerate Vulkan devices: {}", err);
+                    None
+                }
+            };
+            self.blitters.insert(dev, blitter);
+        }
+        self.blitters.get(&dev).cloned().flatten()
+    }
+
+    fn device_info_uncached(&self, dev: u64) -> VkResult<Option<DeviceInfo>> {
+        let Some(device) = self.physical_device(dev)? else {
+            return Ok(None);
+        };
+        let instance = &self.instance.instance;
+        let supported = unsafe { instance.enumerate_device_extension_properties(device)? };
+        let has_driver_props = has_extension(&supported, ash::khr::driver_properties::NAME);
+
+        let mut driver_props = vk::PhysicalDeviceDriverProperties::default();
+        let mut props = vk::PhysicalDeviceProperties2::default();
+        if has_driver_props {
+            props = props.push_next(&mut driver_props);
+        }
+        unsafe { instance.get_physical_device_properties2(device, &mut props) };
+        let props = props.properties;
+
+        Ok(Some(DeviceInfo {
+            name: c_str_to_string(&props.device_name).unwrap_or_default(),
+            vendor_id: props.vendor_id,
+            device_id: props.device_id,
+            driver_name: has_driver_props
+                .then(|| c_str_to_string(&driver_props.driver_name))
+                .flatten(),
+        }))
+    }
+
+    /// Physical device with `dev` as its primary or render node
+    fn physical_device(&self, dev: u64) -> VkResult<Option<vk::PhysicalDevice>> {
+        let instance = &self.instance.instance;
+        let devices = unsafe { instance.enumerate_physical_devices()? };
         for device in devices {
             // Check extension is supported
-            let supported = unsafe {
-                self.instance
-                    .enumerate_device_extension_properties(device)?
-            };
-            if !supported.iter().any(|ext| {
-                CStr::from_bytes_until_nul(bytemuck::cast_slice(&ext.extension_name))
-                    == Ok(ash::ext::physical_device_drm::NAME)
-            }) {
+            let supported = unsafe { instance.enumerate_device_extension_properties(device)? };
+            if !has_extension(&supported, ash::ext::physical_device_drm::NAME) {
                 continue;
             }
 
             let mut drm_props = vk::PhysicalDeviceDrmPropertiesEXT::default();
             let mut props = vk::PhysicalDeviceProperties2::default().push_next(&mut drm_props);
-            unsafe {
-                self.instance
-                    .get_physical_device_properties2(device, &mut props)
-            };
-
-            let device_name =
-                CStr::from_bytes_until_nul(bytemuck::cast_slice(&props.properties.device_name));
+            unsafe { instance.get_physical_device_properties2(device, &mut props) };
 
             let major = rustix::fs::major(dev) as _;
             let minor = rustix::fs::minor(dev) as _;
             if (drm_props.primary_major, drm_props.primary_minor) == (major, minor)
                 || (drm_props.render_major, drm_props.render_minor) == (major, minor)
             {
-                return Ok(device_name
-                    .ok()
-                    .and_then(|x| Some(x.to_str().ok()?.to_owned())));
+                return Ok(Some(device));
             }
         }
 
         Ok(None)
     }
 }
+
+pub fn has_extension(supported: &[vk::ExtensionProperties], name: &CStr) -> bool {
+    supported.iter().any(|ext| {
+        CStr::from_bytes_until_nul(bytemuck::cast_slice(&ext.extension_name)) == Ok(name)
+    })
+}
+
+fn c_str_to_string(chars: &[std::ffi::c_char]) -> Option<String> {
+    CStr::from_bytes_until_nul(bytemuck::cast_slice(chars))
+        .ok()
+        .and_then(|x| Some(x.to_str().ok()?.to_owned()))
+}
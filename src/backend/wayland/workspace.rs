@@ -1,7 +1,9 @@
 use cctk::workspace::{WorkspaceHandler, WorkspaceState};
 use cosmic::cctk;
+use std::mem;
 
 use super::{AppData, CaptureSource, Event};
+use crate::backend::{Cmd, Workspace, WorkspaceGroup, WorkspaceHandle};
 
 impl WorkspaceHandler for AppData {
     fn workspace_state(&mut self) -> &mut WorkspaceState {
@@ -9,15 +11,29 @@ impl WorkspaceHandler for AppData {
     }
 
     fn done(&mut self) {
+        let mut groups = Vec::new();
         let mut workspaces = Vec::new();
 
         // XXX remove capture source for removed workspaces
         // Handle move to another output
 
         for group in self.workspace_state.workspace_groups() {
+            groups.push(WorkspaceGroup {
+                outputs: group.outputs.iter().cloned().collect(),
+                capabilities: group.capabilities,
+            });
             for workspace_handle in &group.workspaces {
                 if let Some(workspace) = self.workspace_state.workspace_info(workspace_handle) {
-                    workspaces.push((group.outputs.iter().cloned().collect(), workspace.clone()));
+                    let workspace = Workspace {
+                        handle: WorkspaceHandle::Wayland(workspace.handle.clone()),
+                        name: workspace.name.clone(),
+                        coordinates: workspace.coordinates.clone(),
+                        state: workspace.state,
+                        capabilities: workspace.capabilities,
+                        cosmic_capabilities: workspace.cosmic_capabilities,
+                        cosmic_state: workspace.cosmic_state,
+                    };
+                    workspaces.push((group.outputs.iter().cloned().collect(), workspace));
 
                     // TODO one capture per output on workspace?
                     self.add_capture_source(CaptureSource::Workspace(workspace_handle.clone()));
@@ -25,7 +41,41 @@ impl WorkspaceHandler for AppData {
             }
         }
 
+        self.sent_workspaces = workspaces
+            .iter()
+            .filter_map(|(_, w)| w.handle.wayland().cloned())
+            .collect();
+        self.send_event(Event::WorkspaceGroups(groups));
         self.send_event(Event::Workspaces(workspaces));
+
+        // Move toplevels to workspaces created for them, in the order requested.
+        // The names requested may not be unique, so workspaces are matched by
+        // being new to the group. If a workspace hasn't been added by the `done`
+        // following the request, the compositor ignored it, so the toplevel isn't
+        // moved.
+        let mut created = Vec::new();
+        for pending in mem::take(&mut self.pending_new_workspaces) {
+            let new_workspace = self
+                .workspace_state
+                .workspace_groups()
+                .find(|g| g.outputs.contains(&pending.output))
+                .and_then(|g| {
+                    g.workspaces
+                        .iter()
+                        .find(|w| !pending.existing.contains(*w) && !created.contains(*w))
+                })
+                .cloned();
+            let Some(workspace_handle) = new_workspace else {
+                log::warn!("Workspace wasn't created; not moving toplevel to it");
+                continue;
+            };
+            created.push(workspace_handle.clone());
+            self.handle_cmd(Cmd::MoveToplevelToWorkspace(
+                pending.toplevel,
+                WorkspaceHandle::Wayland(workspace_handle),
+                pending.output,
+            ));
+        }
     }
 }
 
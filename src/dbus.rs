@@ -2,17 +2,49 @@ use cosmic::iced::futures::StreamExt;
 use cosmic::iced::{self};
 use std::any::TypeId;
 use std::hash::Hash;
-use tokio::sync::broadcast;
+use std::str::FromStr;
+use tokio::sync::{broadcast, watch};
 use tokio_stream::wrappers::BroadcastStream;
 
+const PATH: &str = "/com/system76/CosmicWorkspaces";
+
+/// Alternative ways to show the overview, for `ShowMode`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShowMode {
+    #[default]
+    Default,
+    /// Typing filters toplevels, regardless of `action_on_typing` setting
+    Search,
+}
+
+impl FromStr for ShowMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "default" => Ok(Self::Default),
+            "search" => Ok(Self::Search),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Event {
     Show,
     Hide,
+    Toggle,
+    ShowOnOutput(String),
+    ShowMode(ShowMode),
+    BeginGesture,
+    UpdateGesture(f64),
+    EndGesture(bool),
 }
 
 struct CosmicWorkspaces {
     event_sender: broadcast::Sender<Event>,
+    outputs: watch::Receiver<Vec<String>>,
+    visible: bool,
 }
 
 #[zbus::interface(name = "com.system76.CosmicWorkspaces")]
@@ -25,6 +57,57 @@ impl CosmicWorkspaces {
         let _ = self.event_sender.send(Event::Hide);
     }
 
+    fn toggle(&self) {
+        let _ = self.event_sender.send(Event::Toggle);
+    }
+
+    /// Show only on the output with the given connector name
+    fn show_on_output(&self, name: String) -> zbus::fdo::Result<()> {
+        if !self.outputs.borrow().contains(&name) {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "unknown output '{name}'"
+            )));
+        }
+        let _ = self.event_sender.send(Event::ShowOnOutput(name));
+        Ok(())
+    }
+
+    fn show_mode(&self, mode: &str) -> zbus::fdo::Result<()> {
+        let mode = mode
+            .parse()
+            .map_err(|()| zbus::fdo::Error::InvalidArgs(format!("unknown mode '{mode}'")))?;
+        let _ = self.event_sender.send(Event::ShowMode(mode));
+        Ok(())
+    }
+
+    /// Start showing the overview if hidden, or hiding it if shown, following a
+    /// gesture
+    fn begin_gesture(&self) {
+        let _ = self.event_sender.send(Event::BeginGesture);
+    }
+
+    /// Set how far the gesture has progressed, from 0 to 1
+    fn update_gesture(&self, progress: f64) -> zbus::fdo::Result<()> {
+        if !progress.is_finite() {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "invalid progress '{progress}'"
+            )));
+        }
+        let _ = self.event_sender.send(Event::UpdateGesture(progress));
+        Ok(())
+    }
+
+    /// Finish showing or hiding the overview if `commit`, otherwise return to the
+    /// state before the gesture
+    fn end_gesture(&self, commit: bool) {
+        let _ = self.event_sender.send(Event::EndGesture(commit));
+    }
+
+    #[zbus(property)]
+    fn visible(&self) -> bool {
+        self.visible
+    }
+
     #[zbus(signal)]
     async fn shown(&self, _emitter: &zbus::object_server::SignalEmitter<'_>) -> zbus::Result<()>;
 
@@ -34,37 +117,58 @@ impl CosmicWorkspaces {
 
 #[derive(Clone, Debug)]
 pub struct Interface {
-    emitter: zbus::object_server::SignalEmitter<'static>,
     event_sender: broadcast::Sender<Event>,
+    outputs_sender: watch::Sender<Vec<String>>,
+    visible_sender: watch::Sender<bool>,
 }
 
 impl Interface {
     pub async fn new(conn: zbus::Connection) -> zbus::Result<Self> {
-        let event_sender = broadcast::Sender::new(8);
+        // Large enough to not drop events with frequent gesture updates
+        let event_sender = broadcast::Sender::new(64);
+        let (outputs_sender, outputs) = watch::channel(Vec::new());
         conn.object_server()
             .at(
-                "/com/system76/CosmicWorkspaces",
+                PATH,
                 CosmicWorkspaces {
                     event_sender: event_sender.clone(),
+                    outputs,
+                    visible: false,
                 },
             )
             .await?;
+
+        // Update property and emit signals from a single task, so changes are
+        // always seen by clients in the order they happened.
+        let (visible_sender, mut visible_receiver) = watch::channel(false);
+        tokio::spawn(async move {
+            while visible_receiver.changed().await.is_ok() {
+                let visible = *visible_receiver.borrow_and_update();
+                if let Err(err) = update_visible(&conn, visible).await {
+                    log::error!("Failed to update D-Bus visible property: {}", err);
+                }
+            }
+        });
+
         Ok(Interface {
-            emitter: zbus::object_server::SignalEmitter::new(
-                &conn,
-                "/com/system76/CosmicWorkspaces",
-            )
-            .unwrap(),
             event_sender,
+            outputs_sender,
+            visible_sender,
         })
     }
 
-    pub async fn shown(&self) -> zbus::Result<()> {
-        self.emitter.shown(&self.emitter).await
+    /// Update `Visible` property, and emit `Shown` or `Hidden` if changed
+    pub fn set_visible(&self, visible: bool) {
+        self.visible_sender.send_if_modified(|value| {
+            let modified = *value != visible;
+            *value = visible;
+            modified
+        });
     }
 
-    pub async fn hidden(&self) -> zbus::Result<()> {
-        self.emitter.hidden(&self.emitter).await
+    /// Set connector names of outputs `ShowOnOutput` accepts
+    pub fn set_outputs(&self, outputs: Vec<String>) {
+        self.outputs_sender.send_replace(outputs);
     }
 
     pub fn subscription(&self) -> iced::Subscription<Event> {
@@ -88,3 +192,22 @@ impl Interface {
         )
     }
 }
+
+async fn update_visible(conn: &zbus::Connection, visible: bool) -> zbus::Result<()> {
+    let iface_ref = conn
+        .object_server()
+        .interface::<_, CosmicWorkspaces>(PATH)
+        .await?;
+    let mut iface = iface_ref.get_mut().await;
+    if iface.visible == visible {
+        return Ok(());
+    }
+    iface.visible = visible;
+    let emitter = iface_ref.signal_emitter();
+    iface.visible_changed(emitter).await?;
+    if visible {
+        iface.shown(emitter).await
+    } else {
+        iface.hidden(emitter).await
+    }
+}
@@ -5,27 +5,21 @@ use cosmic::desktop::fde;
 use itertools::Itertools;
 use std::path::PathBuf;
 
-pub async fn icon_for_app_id(app_id: String) -> Option<PathBuf> {
-    tokio::task::spawn_blocking(|| {
-        Some(
-            desktop_info_for_app_ids(vec![app_id])
-                .into_iter()
-                .next()?
-                .icon,
-        )
-    })
-    .await
-    .unwrap()
+pub async fn desktop_info_for_app_id(app_id: String) -> Option<DesktopInfo> {
+    tokio::task::spawn_blocking(|| desktop_info_for_app_ids(vec![app_id]).into_iter().next())
+        .await
+        .unwrap()
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Default)]
-struct DesktopInfo {
+pub struct DesktopInfo {
     id: String,
     wm_class: Option<String>,
-    icon: PathBuf,
+    pub icon: PathBuf,
     exec: String,
-    name: String,
+    /// Name from desktop entry; empty if no desktop entry was found
+    pub name: String,
     path: PathBuf,
 }
 
@@ -6,7 +6,7 @@ use cosmic::iced::clipboard::mime::AsMimeTypes;
 use std::borrow::Cow;
 use std::sync::LazyLock;
 
-use crate::backend::{ExtForeignToplevelHandleV1, ExtWorkspaceHandleV1};
+use crate::backend::{ToplevelHandle, WorkspaceHandle};
 
 // Include `pid` in mime. Want to drag between our surfaces, but not another
 // process, if we use Wayland object ids.
@@ -19,8 +19,8 @@ static TOPLEVEL_MIME: LazyLock<String> =
 #[derive(Clone, Debug)]
 pub enum DragSurface {
     #[allow(dead_code)]
-    Workspace(ExtWorkspaceHandleV1),
-    Toplevel(ExtForeignToplevelHandleV1),
+    Workspace(WorkspaceHandle),
+    Toplevel(ToplevelHandle),
 }
 
 // TODO store protocol object id?
@@ -120,35 +120,31 @@ impl TryFrom<(Vec<u8>, std::string::String)> for Drag {
 #[derive(Clone, Debug, PartialEq)]
 #[repr(u8)]
 pub enum DropTarget {
-    WorkspaceSidebarEntry(ExtWorkspaceHandleV1, wl_output::WlOutput),
-    WorkspaceSidebarDragPlaceholder(ExtWorkspaceHandleV1, wl_output::WlOutput),
-    OutputToplevels(ExtWorkspaceHandleV1, wl_output::WlOutput),
-    #[allow(dead_code)]
+    WorkspaceSidebarEntry(WorkspaceHandle, wl_output::WlOutput),
+    WorkspaceSidebarDragPlaceholder(WorkspaceHandle, wl_output::WlOutput),
+    OutputToplevels(WorkspaceHandle, wl_output::WlOutput),
     WorkspacesBar(wl_output::WlOutput),
 }
 
 impl DropTarget {
     /// Encode as a u64 for iced/smithay_sctk to associate drag destination area with widget.
+    ///
+    /// A workspace that spans multiple outputs has an entry in each output's sidebar,
+    /// so the output id is included (truncated to 24 bits, which is plenty for
+    /// client-side object ids).
     pub fn drag_id(&self) -> u64 {
         // https://doc.rust-lang.org/std/mem/fn.discriminant.html#accessing-the-numeric-value-of-the-discriminant
         let discriminant = unsafe { *<*const _>::from(self).cast::<u8>() };
         match self {
-            Self::WorkspaceSidebarEntry(workspace, _output) => {
-                // TODO consider workspace that span multiple outputs?
-                let id = workspace.id().protocol_id();
-                (u64::from(discriminant) << 32) | u64::from(id)
-            }
-            Self::WorkspaceSidebarDragPlaceholder(workspace, _output) => {
-                let id = workspace.id().protocol_id();
-                (u64::from(discriminant) << 32) | u64::from(id)
-            }
-            Self::OutputToplevels(_workspace, output) => {
-                let id = output.id().protocol_id();
-                (u64::from(discriminant) << 32) | u64::from(id)
+            Self::WorkspaceSidebarEntry(workspace, output)
+            | Self::WorkspaceSidebarDragPlaceholder(workspace, output) => {
+                let id = workspace.protocol_id();
+                let output_id = output.id().protocol_id() & 0xff_ffff;
+                (u64::from(discriminant) << 56) | (u64::from(output_id) << 32) | u64::from(id)
             }
-            Self::WorkspacesBar(output) => {
+            Self::OutputToplevels(_workspace, output) | Self::WorkspacesBar(output) => {
                 let id = output.id().protocol_id();
-                (u64::from(discriminant) << 32) | u64::from(id)
+                (u64::from(discriminant) << 56) | u64::from(id)
             }
         }
     }
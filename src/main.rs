@@ -3,13 +3,15 @@
 
 #![allow(clippy::single_match)]
 
-use cctk::cosmic_protocols::toplevel_management::v1::client::zcosmic_toplevel_manager_v1;
+use cctk::cosmic_protocols::toplevel_management::v1::client::zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1 as ToplevelCapability;
 use cctk::cosmic_protocols::workspace::v2::client::zcosmic_workspace_handle_v2;
 use cctk::sctk::shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer};
 use cctk::wayland_client::protocol::wl_output;
 use cctk::wayland_client::{Connection, Proxy};
-use cctk::wayland_protocols::ext::workspace::v1::client::ext_workspace_handle_v1;
-use clap::Parser;
+use cctk::wayland_protocols::ext::workspace::v1::client::{
+    ext_workspace_group_handle_v1, ext_workspace_handle_v1,
+};
+use clap::{Parser, Subcommand};
 use cosmic::app::{Application, CosmicFlags};
 use cosmic::iced::clipboard::dnd::{DndEvent, SourceEvent};
 use cosmic::iced::event::wayland::{Event as WaylandEvent, LayerEvent, OutputEvent};
@@ -30,44 +32,165 @@ use cosmic_config::CosmicConfigEntry;
 use cosmic_config::cosmic_config_derive::CosmicConfigEntry;
 use cosmic_panel_config::{CosmicPanelConfig, CosmicPanelContainerConfigEntry, PanelAnchor};
 use i18n_embed::DesktopLanguageRequester;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 use std::process::Command;
-use std::time::Duration;
-use std::{mem, str};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{fmt, mem, str};
 
+mod animation;
 mod dbus;
 mod desktop_info;
 #[macro_use]
 mod localize;
 mod backend;
+#[cfg(test)]
+mod tests;
 mod view;
-use backend::{ExtForeignToplevelHandleV1, ExtWorkspaceHandleV1, ToplevelInfo};
+use backend::{ToplevelHandle, ToplevelInfo, WorkspaceHandle};
 mod dnd;
 mod utils;
 mod widgets;
 use dnd::{DragSurface, DragToplevel, DragWorkspace, DropTarget};
 
 const SCROLL_RATE_LIMIT: Duration = Duration::from_millis(200);
+/// Memory to use for keeping previews that aren't being captured, to show when
+/// the overview is opened again
+const PREVIEW_CACHE_BYTES: usize = 128 * 1024 * 1024;
+const APP_ID: &str = "com.system76.CosmicWorkspaces";
+const CONFIG_VERSION: u64 = 1;
+
+/// Behavior when typing while the overview is open
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+enum ActionOnTyping {
+    /// Use `action_on_typing` from the compositor config (open launcher, etc.)
+    #[default]
+    Compositor,
+    /// Filter toplevel previews by typed text
+    FilterWindows,
+}
+
+/// Algorithm used to arrange toplevel previews
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+enum ToplevelLayoutKind {
+    /// A single row
+    SingleRow,
+    /// One row, or two if that allows larger previews
+    #[default]
+    TwoRows,
+    /// Up to this many rows, whichever allows the largest previews
+    Rows(u32),
+    /// As many rows as allows the largest previews
+    Grid,
+    /// Where toplevels are on the output, moved apart so they don't overlap
+    Natural,
+}
+
+/// Direction rows of toplevel previews are laid out in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+enum ToplevelLayoutAxis {
+    /// Rows
+    #[default]
+    Horizontal,
+    /// Columns, which may suit portrait screens
+    Vertical,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+struct ToplevelLayoutConfig {
+    kind: ToplevelLayoutKind,
+    axis: ToplevelLayoutAxis,
+    /// Space between previews, in logical pixels
+    spacing: u32,
+}
+
+impl Default for ToplevelLayoutConfig {
+    fn default() -> Self {
+        Self {
+            kind: ToplevelLayoutKind::default(),
+            axis: ToplevelLayoutAxis::default(),
+            spacing: 16,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Default, PartialEq, CosmicConfigEntry)]
 struct CosmicWorkspacesConfig {
     show_workspace_number: bool,
     show_workspace_name: bool,
+    action_on_typing: ActionOnTyping,
+    /// Workspace names set by the user, by coordinates, used if the compositor
+    /// doesn't support renaming workspaces
+    workspace_names: BTreeMap<Vec<u32>, String>,
+    toplevel_layout: ToplevelLayoutConfig,
+    /// Show and hide the overview, and move previews, without animating
+    reduced_motion: bool,
+    /// How often previews are updated while the overview is shown
+    capture_rates: backend::CaptureRates,
 }
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
-pub struct Args {}
+pub struct Args {
+    /// Backend providing workspaces, toplevels and previews
+    #[arg(long, value_enum, default_value_t)]
+    backend: backend::BackendKind,
+    #[command(subcommand)]
+    subcommand: Option<WorkspaceCommands>,
+}
 
-#[derive(Default, Debug, Clone)]
-pub struct WorkspaceCommands;
+/// Commands forwarded to the running instance with D-Bus activation
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum WorkspaceCommands {
+    /// Show the workspaces overview
+    Show {
+        /// Show only on the output with this connector name
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Hide the workspaces overview
+    Hide,
+    /// Show the workspaces overview if hidden, otherwise hide it
+    Toggle {
+        /// Show only on the output with this connector name
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
 
-#[allow(clippy::to_string_trait_impl)]
-impl ToString for WorkspaceCommands {
-    fn to_string(&self) -> String {
-        String::new()
+// Encoded as action name for `Details::ActivateAction`
+impl fmt::Display for WorkspaceCommands {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, output) = match self {
+            Self::Show { output } => ("show", output),
+            Self::Hide => ("hide", &None),
+            Self::Toggle { output } => ("toggle", output),
+        };
+        match output {
+            Some(output) => write!(f, "{}:{}", name, output),
+            None => write!(f, "{}", name),
+        }
+    }
+}
+
+impl FromStr for WorkspaceCommands {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (name, output) = match s.split_once(':') {
+            Some((name, output)) => (name, Some(output.to_string())),
+            None => (s, None),
+        };
+        match (name, output) {
+            ("show", output) => Ok(Self::Show { output }),
+            ("hide", None) => Ok(Self::Hide),
+            ("toggle", output) => Ok(Self::Toggle { output }),
+            _ => Err(()),
+        }
     }
 }
 
@@ -76,7 +199,7 @@ impl CosmicFlags for Args {
     type Args = Vec<String>;
 
     fn action(&self) -> Option<&WorkspaceCommands> {
-        None
+        self.subcommand.as_ref()
     }
 }
 
@@ -85,11 +208,10 @@ enum Msg {
     WaylandEvent(WaylandEvent),
     Wayland(backend::Event),
     Close,
-    ActivateWorkspace(ExtWorkspaceHandleV1),
-    #[allow(dead_code)]
-    CloseWorkspace(ExtWorkspaceHandleV1),
-    ActivateToplevel(ExtForeignToplevelHandleV1),
-    CloseToplevel(ExtForeignToplevelHandleV1),
+    ActivateWorkspace(WorkspaceHandle),
+    CloseWorkspace(WorkspaceHandle),
+    ActivateToplevel(ToplevelHandle),
+    CloseToplevel(ToplevelHandle),
     StartDrag(DragSurface),
     DndEnter(DropTarget, f64, f64, Vec<String>),
     DndLeave(DropTarget),
@@ -99,23 +221,57 @@ enum Msg {
     #[allow(dead_code)]
     DndWorkspaceDrop(DragWorkspace),
     SourceFinished,
-    #[allow(dead_code)]
-    NewWorkspace,
+    NewWorkspace(wl_output::WlOutput),
     CompConfig(Box<CosmicCompConfig>),
     Config(CosmicWorkspacesConfig),
     BgConfig(cosmic_bg_config::state::State),
-    UpdateToplevelIcon(String, Option<PathBuf>),
+    UpdateToplevelDesktopInfo(String, Option<desktop_info::DesktopInfo>),
     OnScroll(wl_output::WlOutput, ScrollDelta),
-    TogglePinned(ExtWorkspaceHandleV1),
-    EnteredWorkspaceSidebarEntry(ExtWorkspaceHandleV1, bool),
+    TogglePinned(WorkspaceHandle),
+    EnteredWorkspaceSidebarEntry(WorkspaceHandle, bool),
+    EnteredToplevelPreview(ToplevelHandle, bool),
+    /// Size toplevel previews are shown at on the output
+    ToplevelPreviewSizes(wl_output::WlOutput, Vec<(ToplevelHandle, Size)>),
     DbusInterface(zbus::Result<dbus::Interface>),
     DBus(dbus::Event),
     PanelContainerEntries(Vec<String>),
     PanelConfig(CosmicPanelConfig),
     ActionOnTyping(String),
+    SearchBackspace,
+    Escape,
+    SetFocus(Option<Focus>),
+    FocusNext(SurfaceId, bool),
+    ActivateFocused,
+    CloseFocused,
+    StartRename(WorkspaceHandle),
+    RenameFocused,
+    RenameInput(String),
+    SubmitRename,
+    ToplevelContextMenu(Option<ToplevelHandle>),
+    ToplevelAction(ToplevelHandle, ToplevelAction),
+    AnimationFrame(Instant),
     Ignore,
 }
 
+/// Action from the context menu of a toplevel
+#[derive(Clone, Debug)]
+enum ToplevelAction {
+    Minimize(bool),
+    Maximize(bool),
+    Fullscreen(bool),
+    Sticky(bool),
+    MoveToWorkspace(WorkspaceHandle, wl_output::WlOutput),
+    /// Move to the active workspace of the output
+    MoveToOutput(wl_output::WlOutput),
+}
+
+/// Workspace or toplevel that has keyboard focus
+#[derive(Clone, Debug, PartialEq)]
+enum Focus {
+    Toplevel(ToplevelHandle),
+    Workspace(WorkspaceHandle),
+}
+
 #[derive(Clone, Debug)]
 struct Workspace {
     info: backend::Workspace,
@@ -127,7 +283,7 @@ struct Workspace {
 }
 
 impl Workspace {
-    fn handle(&self) -> &ExtWorkspaceHandleV1 {
+    fn handle(&self) -> &WorkspaceHandle {
         &self.info.handle
     }
 
@@ -142,14 +298,77 @@ impl Workspace {
             .cosmic_state
             .contains(zcosmic_workspace_handle_v2::State::Pinned)
     }
+
+    fn can_remove(&self) -> bool {
+        self.info
+            .capabilities
+            .contains(ext_workspace_handle_v1::WorkspaceCapabilities::Remove)
+    }
+
+    /// Whether the workspace can be moved to another output's workspace group
+    fn can_assign(&self) -> bool {
+        self.info
+            .capabilities
+            .contains(ext_workspace_handle_v1::WorkspaceCapabilities::Assign)
+    }
+
+    /// Whether the compositor supports renaming this workspace
+    fn can_rename(&self) -> bool {
+        self.info
+            .cosmic_capabilities
+            .contains(zcosmic_workspace_handle_v2::WorkspaceCapabilities::Rename)
+    }
+
+    /// Name set by the user in `workspace_names`, if the compositor doesn't
+    /// support renaming
+    fn local_name<'a>(&self, workspace_names: &'a BTreeMap<Vec<u32>, String>) -> Option<&'a str> {
+        if self.can_rename() {
+            return None;
+        }
+        workspace_names
+            .get(&self.info.coordinates)
+            .map(String::as_str)
+    }
+
+    /// Name shown in the sidebar
+    fn display_name(&self, workspace_names: &BTreeMap<Vec<u32>, String>) -> String {
+        match self.local_name(workspace_names) {
+            Some(name) => name.to_string(),
+            None => fl!("workspace", number = self.info.name.as_str()),
+        }
+    }
+
+    /// Name the rename input starts with
+    fn rename_name(&self, workspace_names: &BTreeMap<Vec<u32>, String>) -> String {
+        self.local_name(workspace_names)
+            .unwrap_or(&self.info.name)
+            .to_string()
+    }
 }
 
 #[derive(Clone, Debug)]
 struct Toplevel {
-    handle: ExtForeignToplevelHandleV1,
+    handle: ToplevelHandle,
     info: ToplevelInfo,
     img: Option<backend::CaptureImage>,
     icon: Option<PathBuf>,
+    /// Name from desktop entry
+    app_name: Option<String>,
+}
+
+impl Toplevel {
+    /// Case-insensitive match of `search` against title, app id, or app name
+    fn matches_search(&self, search: &str) -> bool {
+        let search = search.to_lowercase();
+        [
+            Some(&self.info.title),
+            Some(&self.info.app_id),
+            self.app_name.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|s| s.to_lowercase().contains(&search))
+    }
 }
 
 #[derive(Clone)]
@@ -158,6 +377,8 @@ struct Output {
     name: String,
     width: i32,
     height: i32,
+    /// Integer scale factor, rounded up if fractional
+    scale: i32,
 }
 
 #[derive(Debug)]
@@ -176,13 +397,14 @@ struct Conf {
 
 #[derive(Default)]
 struct App {
+    backend: backend::BackendKind,
     capture_filter: backend::CaptureFilter,
     layer_surfaces: HashMap<SurfaceId, LayerSurface>,
     outputs: Vec<Output>,
+    workspace_groups: Vec<backend::WorkspaceGroup>,
     workspaces: Workspaces,
     toplevels: Toplevels,
-    toplevel_capabilities:
-        Vec<zcosmic_toplevel_manager_v1::ZcosmicToplelevelManagementCapabilitiesV1>,
+    toplevel_capabilities: Vec<ToplevelCapability>,
     conn: Option<Connection>,
     visible: bool,
     wayland_cmd_sender: Option<calloop::channel::Sender<backend::Cmd>>,
@@ -194,17 +416,33 @@ struct App {
     dbus_interface: Option<dbus::Interface>,
     panel_configs: HashMap<String, Option<CosmicPanelConfig>>,
     action_on_typing_activated: bool,
+    focus: Option<Focus>,
+    /// Text typed to filter toplevels, with `ActionOnTyping::FilterWindows`
+    search: String,
+    /// If set, overview is shown only on the output with this name
+    only_output: Option<String>,
+    show_mode: dbus::ShowMode,
+    /// Workspace being renamed, and the name entered so far
+    renaming: Option<(WorkspaceHandle, String)>,
+    /// Toplevel with an open context menu
+    context_menu: Option<ToplevelHandle>,
+    /// Overview being animated in or out
+    transition: Option<animation::Transition>,
+    /// Toplevel preview under the cursor
+    hovered_toplevel: Option<ToplevelHandle>,
+    /// Size each toplevel preview is shown at, in buffer pixels
+    preview_sizes: HashMap<ToplevelHandle, (u32, u32)>,
 }
 
 #[derive(Debug, Default)]
 struct Workspaces(Vec<Workspace>);
 
 impl Workspaces {
-    fn for_handle(&self, handle: &ExtWorkspaceHandleV1) -> Option<&Workspace> {
+    fn for_handle(&self, handle: &WorkspaceHandle) -> Option<&Workspace> {
         self.0.iter().find(|i| i.handle() == handle)
     }
 
-    fn for_handle_mut(&mut self, handle: &ExtWorkspaceHandleV1) -> Option<&mut Workspace> {
+    fn for_handle_mut(&mut self, handle: &WorkspaceHandle) -> Option<&mut Workspace> {
         self.0.iter_mut().find(|i| i.handle() == handle)
     }
 
@@ -220,7 +458,7 @@ impl Workspaces {
 struct Toplevels(Vec<Toplevel>);
 
 impl Toplevels {
-    fn for_handle_mut(&mut self, handle: &ExtForeignToplevelHandleV1) -> Option<&mut Toplevel> {
+    fn for_handle_mut(&mut self, handle: &ToplevelHandle) -> Option<&mut Toplevel> {
         self.0.iter_mut().find(|i| &i.handle == handle)
     }
 }
@@ -259,6 +497,20 @@ impl App {
         }
     }
 
+    fn run_command(&mut self, cmd: WorkspaceCommands) -> Task<cosmic::Action<Msg>> {
+        match cmd {
+            WorkspaceCommands::Show { output } => self.show_on_output(output),
+            WorkspaceCommands::Hide => self.hide(),
+            WorkspaceCommands::Toggle { output } => {
+                if self.visible {
+                    self.hide()
+                } else {
+                    self.show_on_output(output)
+                }
+            }
+        }
+    }
+
     fn toggle(&mut self) -> Task<cosmic::Action<Msg>> {
         if self.visible {
             self.hide()
@@ -268,35 +520,81 @@ impl App {
     }
 
     fn show(&mut self) -> Task<cosmic::Action<Msg>> {
-        if !self.visible {
-            self.visible = true;
-            let outputs = self.outputs.clone();
-            let cmd = Task::batch(
-                outputs
-                    .into_iter()
-                    .map(|output| self.create_surface(output.handle))
-                    .collect::<Vec<_>>(),
-            );
-            self.update_capture_filter();
+        self.show_on_output(None)
+    }
 
-            if let Some(interface) = self.dbus_interface.clone() {
-                tokio::spawn(async move {
-                    let _ = interface.shown().await;
-                });
+    /// Show on all outputs, or only the output named `only_output` if set
+    ///
+    /// If already shown, surfaces are created or destroyed to match the new
+    /// set of outputs.
+    fn show_on_output(&mut self, only_output: Option<String>) -> Task<cosmic::Action<Msg>> {
+        let was_visible = self.visible;
+        if was_visible && self.only_output == only_output {
+            return Task::none();
+        }
+        self.visible = true;
+        self.only_output = only_output;
+        let mut closing = None;
+        if !was_visible {
+            let now = Instant::now();
+            // Surfaces are still shown if hiding was being animated
+            closing = self.transition.take_if(|t| !t.opening);
+            self.transition = if self.conf.config.reduced_motion {
+                None
+            } else {
+                Some(match closing {
+                    Some(transition) => transition.reverse(now),
+                    None => animation::Transition::opening(now),
+                })
+            };
+        }
+        // Surfaces already being destroyed can't be reused
+        let reuse_surfaces = was_visible || closing.is_some();
+        let outputs = self.shown_outputs().cloned().collect::<Vec<_>>();
+        let mut tasks = Vec::new();
+        if reuse_surfaces {
+            let stale_outputs = self
+                .layer_surfaces
+                .values()
+                .map(|surface| surface.output.clone())
+                .filter(|output| !outputs.contains(output))
+                .collect::<Vec<_>>();
+            for output in stale_outputs {
+                tasks.push(self.destroy_surface(&output));
+            }
+        }
+        for output in outputs {
+            if !reuse_surfaces || !self.layer_surfaces.values().any(|s| s.output == output) {
+                tasks.push(self.create_surface(output));
             }
+        }
+        self.update_capture_filter();
 
-            cmd
-        } else {
-            Task::none()
+        if !was_visible && let Some(interface) = &self.dbus_interface {
+            interface.set_visible(true);
+        }
+
+        Task::batch(tasks)
+    }
+
+    fn update_dbus_outputs(&self) {
+        if let Some(interface) = &self.dbus_interface {
+            interface.set_outputs(self.outputs.iter().map(|o| o.name.clone()).collect());
         }
     }
 
+    /// Outputs the overview is shown on, when visible
+    fn shown_outputs(&self) -> impl Iterator<Item = &wl_output::WlOutput> {
+        self.outputs
+            .iter()
+            .filter(|output| self.only_output.as_ref().is_none_or(|o| *o == output.name))
+            .map(|output| &output.handle)
+    }
+
     // Close all shell surfaces
     fn hide(&mut self) -> Task<cosmic::Action<Msg>> {
-        if let Some(interface) = self.dbus_interface.clone() {
-            tokio::spawn(async move {
-                let _ = interface.hidden().await;
-            });
+        if let Some(interface) = &self.dbus_interface {
+            interface.set_visible(false);
         }
 
         if self.action_on_typing_activated {
@@ -327,8 +625,28 @@ impl App {
         self.action_on_typing_activated = false;
 
         self.visible = false;
-        self.update_capture_filter();
+        self.focus = None;
+        self.search.clear();
+        self.only_output = None;
+        self.show_mode = dbus::ShowMode::Default;
+        self.renaming = None;
+        self.context_menu = None;
+        self.hovered_toplevel = None;
         self.drag_surface = None;
+        if self.conf.config.reduced_motion || self.layer_surfaces.is_empty() {
+            self.transition = None;
+            self.update_capture_filter();
+            self.destroy_surfaces()
+        } else {
+            // Surfaces are destroyed when the transition finishes
+            let now = Instant::now();
+            let progress = self.transition.map_or(1., |t| t.progress(now));
+            self.transition = Some(animation::Transition::at_progress(progress, false, now));
+            Task::none()
+        }
+    }
+
+    fn destroy_surfaces(&self) -> Task<cosmic::Action<Msg>> {
         Task::batch(
             self.layer_surfaces
                 .keys()
@@ -337,41 +655,281 @@ impl App {
         )
     }
 
+    /// Show or hide the overview progressively, following a gesture
+    fn begin_gesture(&mut self) -> Task<cosmic::Action<Msg>> {
+        // Shown or hidden all at once when the gesture ends
+        if self.conf.config.reduced_motion {
+            return Task::none();
+        }
+        let opening = !self.visible;
+        let task = if opening { self.show() } else { Task::none() };
+        let from = self.transition_progress();
+        self.transition = Some(animation::Transition::gesture(from, opening));
+        task
+    }
+
+    fn end_gesture(&mut self, commit: bool) -> Task<cosmic::Action<Msg>> {
+        match self.transition.filter(|t| t.is_gesture()) {
+            Some(transition) if transition.opening == commit => {
+                let now = Instant::now();
+                self.transition = Some(animation::Transition::at_progress(
+                    transition.progress(now),
+                    true,
+                    now,
+                ));
+                Task::none()
+            }
+            Some(_) => self.hide(),
+            None if commit && self.conf.config.reduced_motion => self.toggle(),
+            None => Task::none(),
+        }
+    }
+
+    /// How far the overview is shown, from 0 (hidden) to 1 (fully shown)
+    fn transition_progress(&self) -> f32 {
+        self.transition
+            .map_or(1., |transition| transition.progress(Instant::now()))
+    }
+
+    fn can_create_workspace(&self, output: &wl_output::WlOutput) -> bool {
+        self.workspace_groups.iter().any(|group| {
+            group.outputs.contains(output)
+                && group
+                    .capabilities
+                    .contains(ext_workspace_group_handle_v1::GroupCapabilities::CreateWorkspace)
+        })
+    }
+
+    fn has_toplevel_capability(&self, capability: ToplevelCapability) -> bool {
+        self.toplevel_capabilities.contains(&capability)
+    }
+
+    /// Name for a new workspace at the end of the output's workspaces, skipping
+    /// numbers already used as a name on the output
+    fn new_workspace_name(&self, output: &wl_output::WlOutput) -> String {
+        let names = self
+            .workspaces
+            .for_output(output)
+            .map(|w| w.info.name.as_str())
+            .collect::<HashSet<_>>();
+        (self.workspaces.for_output(output).count() + 1..)
+            .map(|n| n.to_string())
+            .find(|name| !names.contains(name.as_str()))
+            .unwrap()
+    }
+
+    /// Rename with the compositor if supported, or otherwise store name in config.
+    ///
+    /// An empty name resets a name stored in config.
+    fn rename_workspace(&mut self, handle: &WorkspaceHandle, name: &str) {
+        let Some(workspace) = self.workspaces.for_handle(handle) else {
+            return;
+        };
+        if workspace.can_rename() {
+            if !name.is_empty() {
+                self.send_wayland_cmd(backend::Cmd::RenameWorkspace(
+                    handle.clone(),
+                    name.to_string(),
+                ));
+            }
+            return;
+        }
+
+        let mut workspace_names = self.conf.config.workspace_names.clone();
+        if name.is_empty() {
+            workspace_names.remove(&workspace.info.coordinates);
+        } else {
+            workspace_names.insert(workspace.info.coordinates.clone(), name.to_string());
+        }
+        let res = cosmic_config::Config::new(App::APP_ID, CONFIG_VERSION).and_then(|config| {
+            self.conf
+                .config
+                .set_workspace_names(&config, workspace_names)
+        });
+        if let Err(err) = res {
+            log::error!("Failed to save workspace name: {}", err);
+        }
+    }
+
     fn send_wayland_cmd(&self, cmd: backend::Cmd) {
         if let Some(sender) = self.wayland_cmd_sender.as_ref() {
             sender.send(cmd).unwrap();
         }
     }
 
+    /// Toplevels shown in the previews for `output`, on the active workspace
+    /// and matching the search filter
+    fn toplevels_for_output<'a>(
+        &'a self,
+        output: &'a wl_output::WlOutput,
+    ) -> impl Iterator<Item = &'a Toplevel> + 'a {
+        self.toplevels.0.iter().filter(|i| {
+            if !i.info.output.contains(output) {
+                return false;
+            }
+
+            if !self.search.is_empty() && !i.matches_search(&self.search) {
+                return false;
+            }
+
+            i.info.workspace.iter().any(|workspace| {
+                self.workspaces
+                    .for_handle(workspace)
+                    .is_some_and(|x| x.is_active())
+            })
+        })
+    }
+
+    /// Move keyboard focus to the next (or previous) toplevel or workspace on
+    /// `output`, in the order of the previews followed by the sidebar.
+    fn focus_next(&mut self, output: &wl_output::WlOutput, reverse: bool) {
+        let mut order = self
+            .toplevels_for_output(output)
+            .map(|t| Focus::Toplevel(t.handle.clone()))
+            .collect::<Vec<_>>();
+        order.extend(
+            self.workspaces
+                .for_output(output)
+                .map(|w| Focus::Workspace(w.handle().clone())),
+        );
+        if order.is_empty() {
+            return;
+        }
+        let current = self
+            .focus
+            .as_ref()
+            .and_then(|focus| order.iter().position(|x| x == focus));
+        let next = match (current, reverse) {
+            (Some(idx), false) => (idx + 1) % order.len(),
+            (Some(idx), true) => (idx + order.len() - 1) % order.len(),
+            (None, false) => 0,
+            (None, true) => order.len() - 1,
+        };
+        self.focus = Some(order.swap_remove(next));
+        self.update_active_toplevels();
+    }
+
+    /// Focus the first toplevel matching the search, so it is activated by Enter
+    fn focus_top_search_match(&mut self) {
+        self.focus = self
+            .outputs
+            .iter()
+            .find_map(|output| self.toplevels_for_output(&output.handle).next())
+            .map(|t| Focus::Toplevel(t.handle.clone()));
+        self.update_active_toplevels();
+    }
+
+    /// Toplevels that are hovered or have keyboard focus, and are captured at a
+    /// higher rate
+    fn active_toplevels(&self) -> Vec<ToplevelHandle> {
+        let focused = match &self.focus {
+            Some(Focus::Toplevel(handle)) => Some(handle),
+            _ => None,
+        };
+        let mut active = Vec::new();
+        active.extend(self.hovered_toplevel.clone());
+        active.extend(
+            focused
+                .filter(|h| self.hovered_toplevel.as_ref() != Some(*h))
+                .cloned(),
+        );
+        active
+    }
+
+    /// Update capture filter if the active toplevels changed
+    fn update_active_toplevels(&mut self) {
+        if self.visible && self.active_toplevels() != self.capture_filter.active_toplevels {
+            self.update_capture_filter();
+        }
+    }
+
     fn update_capture_filter(&mut self) {
+        // Keep previews while hiding is animated; updated when it finishes
+        if !self.visible && self.transition.is_some() {
+            return;
+        }
         let mut capture_filter = backend::CaptureFilter::default();
         if self.visible {
-            capture_filter.workspaces_on_outputs =
-                self.outputs.iter().map(|x| x.handle.clone()).collect();
+            capture_filter.workspaces_on_outputs = self.shown_outputs().cloned().collect();
             capture_filter.toplevels_on_workspaces = self
                 .workspaces
                 .0
                 .iter()
-                .filter(|x| x.is_active())
+                .filter(|x| x.is_active() && capture_filter.workspace_outputs_matches(&x.outputs))
                 .map(|x| x.handle().clone())
                 .collect();
+            capture_filter.active_toplevels = self.active_toplevels();
+            capture_filter.toplevel_sizes = self
+                .preview_sizes
+                .iter()
+                .map(|(handle, size)| (handle.clone(), *size))
+                .collect();
+            let max_scale = self
+                .outputs
+                .iter()
+                .filter(|output| {
+                    capture_filter
+                        .workspaces_on_outputs
+                        .contains(&output.handle)
+                })
+                .map(|output| output.scale.max(1) as u32)
+                .max();
+            capture_filter.workspace_size = max_scale.map(|scale| {
+                let (width, height) = view::WORKSPACE_PREVIEW_MIN_SIZE;
+                (width * scale, height * scale)
+            });
         }
+        capture_filter.rates = self.conf.config.capture_rates;
+
+        self.trim_preview_cache(&capture_filter);
+
+        self.capture_filter = capture_filter.clone();
+        self.send_wayland_cmd(backend::Cmd::CaptureFilter(capture_filter));
+    }
+
+    /// Keep the last `CaptureImage` of workspaces and toplevels not matching the
+    /// capture filter, up to `PREVIEW_CACHE_BYTES`, preferring those most likely
+    /// to be shown when the overview is next opened.
+    fn trim_preview_cache(&mut self, capture_filter: &backend::CaptureFilter) {
+        let mut budget = PREVIEW_CACHE_BYTES;
+        let mut keep_cached = |img: &mut Option<backend::CaptureImage>| {
+            if let Some(image) = img {
+                let size = image.size_in_bytes();
+                if size <= budget {
+                    budget -= size;
+                } else {
+                    *img = None;
+                }
+            }
+        };
 
-        // Drop `CaptureImage` for workspaces and toplevels not matching new
-        // filter.
         for workspace in &mut self.workspaces.0 {
             if !capture_filter.workspace_outputs_matches(&workspace.outputs) {
-                workspace.img = None;
-            }
-        }
-        for toplevel in &mut self.toplevels.0 {
-            if !capture_filter.toplevel_matches(&toplevel.info) {
-                toplevel.img = None;
+                keep_cached(&mut workspace.img);
             }
         }
 
-        self.capture_filter = capture_filter.clone();
-        self.send_wayland_cmd(backend::Cmd::CaptureFilter(capture_filter));
+        let active_workspaces = self
+            .workspaces
+            .0
+            .iter()
+            .filter(|w| w.is_active())
+            .map(|w| w.handle().clone())
+            .collect::<Vec<_>>();
+        let (on_active_workspace, other): (Vec<_>, Vec<_>) = self
+            .toplevels
+            .0
+            .iter_mut()
+            .filter(|t| !capture_filter.toplevel_matches(&t.info))
+            .partition(|t| {
+                t.info
+                    .workspace
+                    .iter()
+                    .any(|w| active_workspaces.contains(w))
+            });
+        for toplevel in on_active_workspace.into_iter().chain(other) {
+            keep_cached(&mut toplevel.img);
+        }
     }
 
     fn panel_regions(&self, output_handle: &wl_output::WlOutput) -> iced::Padding {
@@ -427,17 +985,22 @@ impl Application for App {
     type Message = Msg;
     type Executor = cosmic::SingleThreadExecutor;
     type Flags = Args;
-    const APP_ID: &'static str = "com.system76.CosmicWorkspaces";
-
-    fn init(core: cosmic::app::Core, _flags: Self::Flags) -> (Self, Task<cosmic::Action<Msg>>) {
-        (
-            Self {
-                core,
-                scroll: DiscreteScrollState::default().rate_limit(Some(SCROLL_RATE_LIMIT)),
-                ..Default::default()
-            },
-            Task::none(),
-        )
+    const APP_ID: &'static str = APP_ID;
+
+    fn init(core: cosmic::app::Core, flags: Self::Flags) -> (Self, Task<cosmic::Action<Msg>>) {
+        let mut app = Self {
+            core,
+            backend: flags.backend,
+            scroll: DiscreteScrollState::default().rate_limit(Some(SCROLL_RATE_LIMIT)),
+            ..Default::default()
+        };
+        // Outputs aren't known yet, but surfaces are created for them as they are
+        // added if already visible.
+        let task = match flags.subcommand {
+            Some(cmd) => app.run_command(cmd),
+            None => Task::none(),
+        };
+        (app, task)
     }
     // TODO: show panel and dock? Drag?
 
@@ -465,8 +1028,12 @@ impl Application for App {
                                     name: name.clone(),
                                     width,
                                     height,
+                                    scale: info.scale_factor,
                                 });
-                                if self.visible {
+                                self.update_dbus_outputs();
+                                if self.visible
+                                    && self.only_output.as_ref().is_none_or(|o| *o == name)
+                                {
                                     return self.create_surface(output.clone());
                                 }
                             }
@@ -483,17 +1050,22 @@ impl Application for App {
                                 if let Some(name) = info.name {
                                     output.name = name;
                                 }
+                                output.scale = info.scale_factor;
                                 // XXX re-create surface?
+                                self.update_dbus_outputs();
                             }
                         }
                         OutputEvent::Removed => {
                             if let Some(idx) = self.outputs.iter().position(|x| x.handle == output)
                             {
-                                self.outputs.remove(idx);
-                            }
-                            if self.visible {
-                                return self.destroy_surface(&output);
+                                let removed = self.outputs.remove(idx);
+                                self.update_dbus_outputs();
+                                if self.only_output.as_ref() == Some(&removed.name) {
+                                    return self.hide();
+                                }
                             }
+                            // May also be shown while hiding is animated
+                            return self.destroy_surface(&output);
                         }
                     }
                 }
@@ -509,6 +1081,9 @@ impl Application for App {
                     backend::Event::CmdSender(sender) => {
                         self.wayland_cmd_sender = Some(sender);
                     }
+                    backend::Event::WorkspaceGroups(groups) => {
+                        self.workspace_groups = groups;
+                    }
                     backend::Event::Workspaces(mut workspaces) => {
                         workspaces.sort_by(|(_, w1), (_, w2)| w1.coordinates.cmp(&w2.coordinates));
                         let old_workspaces = mem::take(&mut self.workspaces);
@@ -530,23 +1105,23 @@ impl Application for App {
                         }
                         self.update_capture_filter();
                     }
-                    backend::Event::NewToplevel(handle, info) => {
+                    backend::Event::NewToplevel(handle, info, initial) => {
                         log::debug!("New toplevel: {info:?}");
                         let app_id = info.app_id.clone();
                         let icon_task = iced::Task::perform(
-                            desktop_info::icon_for_app_id(app_id.clone()),
-                            move |path| Msg::UpdateToplevelIcon(app_id.clone(), path),
+                            desktop_info::desktop_info_for_app_id(app_id.clone()),
+                            move |info| Msg::UpdateToplevelDesktopInfo(app_id.clone(), info),
                         )
                         .map(cosmic::Action::App);
                         self.toplevels.0.push(Toplevel {
                             icon: None,
+                            app_name: None,
                             handle,
                             info,
                             img: None,
                         });
                         // Close workspaces view if a window spawns while open
-                        #[cfg(not(feature = "mock-backend"))]
-                        if self.visible {
+                        if self.visible && !initial {
                             return Task::batch([icon_task, self.hide()]);
                         }
                         return icon_task;
@@ -557,8 +1132,10 @@ impl Application for App {
                             if toplevel.info.app_id != info.app_id {
                                 let app_id = info.app_id.clone();
                                 task = iced::Task::perform(
-                                    desktop_info::icon_for_app_id(app_id.clone()),
-                                    move |path| Msg::UpdateToplevelIcon(app_id.clone(), path),
+                                    desktop_info::desktop_info_for_app_id(app_id.clone()),
+                                    move |info| {
+                                        Msg::UpdateToplevelDesktopInfo(app_id.clone(), info)
+                                    },
                                 )
                                 .map(cosmic::Action::App);
                             }
@@ -571,6 +1148,10 @@ impl Application for App {
                         {
                             self.toplevels.0.remove(idx);
                         }
+                        self.preview_sizes.remove(&handle);
+                        if self.focus == Some(Focus::Toplevel(handle)) {
+                            self.focus = None;
+                        }
                     }
                     backend::Event::WorkspaceCapture(handle, image) => {
                         //println!("Workspace capture");
@@ -607,26 +1188,25 @@ impl Application for App {
                 self.send_wayland_cmd(backend::Cmd::ActivateWorkspace(workspace_handle));
             }
             Msg::ActivateToplevel(toplevel_handle) => {
-                self.send_wayland_cmd(backend::Cmd::ActivateToplevel(toplevel_handle));
-                return self.hide();
+                if self.has_toplevel_capability(ToplevelCapability::Activate) {
+                    self.send_wayland_cmd(backend::Cmd::ActivateToplevel(toplevel_handle));
+                    return self.hide();
+                }
             }
-            Msg::CloseWorkspace(_workspace_handle) => {
-                // XXX close specific workspace
-                /*
-                if let WorkspaceAmount::Static(n) = &mut self.conf.workspace_config.workspace_amount
+            Msg::CloseWorkspace(workspace_handle) => {
+                if self
+                    .workspaces
+                    .for_handle(&workspace_handle)
+                    .is_some_and(|w| w.can_remove())
                 {
-                    if *n != 1 {
-                        *n -= 1;
-                        self.conf
-                            .cosmic_comp_config
-                            .set("workspaces", &self.conf.workspace_config);
-                    }
+                    self.send_wayland_cmd(backend::Cmd::RemoveWorkspace(workspace_handle));
                 }
-                */
             }
             Msg::CloseToplevel(toplevel_handle) => {
                 // TODO confirmation?
-                self.send_wayland_cmd(backend::Cmd::CloseToplevel(toplevel_handle));
+                if self.has_toplevel_capability(ToplevelCapability::Close) {
+                    self.send_wayland_cmd(backend::Cmd::CloseToplevel(toplevel_handle));
+                }
             }
             Msg::StartDrag(drag_surface) => {
                 self.drag_surface = Some((drag_surface, Default::default()));
@@ -642,8 +1222,11 @@ impl Application for App {
                 }
             }
             Msg::DndToplevelDrop(_toplevel) => {
-                if let Some((DragSurface::Toplevel(handle), _)) = &self.drag_surface {
-                    match self.drop_target.take() {
+                let drop_target = self.drop_target.take();
+                if let Some((DragSurface::Toplevel(handle), _)) = &self.drag_surface
+                    && self.has_toplevel_capability(ToplevelCapability::MoveToWorkspace)
+                {
+                    match drop_target {
                         Some(
                             DropTarget::WorkspaceSidebarEntry(workspace, output)
                             | DropTarget::OutputToplevels(workspace, output),
@@ -654,27 +1237,63 @@ impl Application for App {
                                 output,
                             ));
                         }
-                        Some(
-                            DropTarget::WorkspacesBar(_)
-                            | DropTarget::WorkspaceSidebarDragPlaceholder(_, _),
-                        )
-                        | None => {}
+                        Some(DropTarget::WorkspacesBar(output)) => {
+                            // Reuse the last workspace if it's empty, like the one cosmic-comp
+                            // keeps at the end, instead of creating another
+                            let empty_last_workspace = self
+                                .workspaces
+                                .for_output(&output)
+                                .last()
+                                .filter(|w| {
+                                    !self
+                                        .toplevels
+                                        .0
+                                        .iter()
+                                        .any(|t| t.info.workspace.contains(w.handle()))
+                                })
+                                .map(|w| w.handle().clone());
+                            if let Some(workspace) = empty_last_workspace {
+                                self.send_wayland_cmd(backend::Cmd::MoveToplevelToWorkspace(
+                                    handle.clone(),
+                                    workspace,
+                                    output,
+                                ));
+                            } else if self.can_create_workspace(&output) {
+                                let name = self.new_workspace_name(&output);
+                                self.send_wayland_cmd(backend::Cmd::MoveToplevelToNewWorkspace(
+                                    handle.clone(),
+                                    output,
+                                    name,
+                                ));
+                            }
+                        }
+                        Some(DropTarget::WorkspaceSidebarDragPlaceholder(_, _)) | None => {}
                     }
                 }
             }
-            Msg::NewWorkspace => {
-                /*
-                if let WorkspaceAmount::Static(n) = &mut self.conf.workspace_config.workspace_amount
-                {
-                    *n += 1;
-                    self.conf
-                        .cosmic_comp_config
-                        .set("workspaces", &self.conf.workspace_config);
+            Msg::NewWorkspace(output) => {
+                if self.can_create_workspace(&output) {
+                    let name = self.new_workspace_name(&output);
+                    self.send_wayland_cmd(backend::Cmd::CreateWorkspace(output, name));
                 }
-                */
             }
             Msg::Config(c) => {
+                let rates_changed = c.capture_rates != self.conf.config.capture_rates;
                 self.conf.config = c;
+                if rates_changed {
+                    self.update_capture_filter();
+                }
+            }
+            Msg::AnimationFrame(now) => {
+                if let Some(transition) = self.transition
+                    && transition.is_finished(now)
+                {
+                    self.transition = None;
+                    if !transition.opening {
+                        self.update_capture_filter();
+                        return self.destroy_surfaces();
+                    }
+                }
             }
             Msg::CompConfig(c) => {
                 self.conf.workspace_config = c.workspaces;
@@ -682,10 +1301,14 @@ impl Application for App {
             Msg::BgConfig(c) => {
                 self.conf.bg = c;
             }
-            Msg::UpdateToplevelIcon(app_id, path) => {
+            Msg::UpdateToplevelDesktopInfo(app_id, info) => {
                 for toplevel in self.toplevels.0.iter_mut() {
                     if toplevel.info.app_id == app_id {
-                        toplevel.icon = path.clone();
+                        toplevel.icon = info.as_ref().map(|i| i.icon.clone());
+                        toplevel.app_name = info
+                            .as_ref()
+                            .map(|i| i.name.clone())
+                            .filter(|name| !name.is_empty());
                     }
                 }
             }
@@ -711,15 +1334,22 @@ impl Application for App {
                 if let Some((DragSurface::Workspace(handle), _)) = &self.drag_surface {
                     match self.drop_target.take() {
                         Some(
-                            DropTarget::WorkspaceSidebarEntry(other_handle, _output)
-                            | DropTarget::WorkspaceSidebarDragPlaceholder(other_handle, _output),
+                            DropTarget::WorkspaceSidebarEntry(other_handle, output)
+                            | DropTarget::WorkspaceSidebarDragPlaceholder(other_handle, output),
                         ) => {
                             let workspace = self.workspaces.for_handle(handle);
                             let other_workspace = self.workspaces.for_handle(&other_handle);
                             if let (Some(workspace), Some(other_workspace)) =
                                 (workspace, other_workspace)
                             {
-                                if workspace.outputs == other_workspace.outputs
+                                if !workspace.outputs.contains(&output) && workspace.can_assign() {
+                                    // Dropped on another output's sidebar
+                                    self.send_wayland_cmd(backend::Cmd::MoveWorkspaceToOutput(
+                                        handle.clone(),
+                                        output,
+                                        Some(other_handle),
+                                    ));
+                                } else if workspace.outputs == other_workspace.outputs
                                     && workspace.info.coordinates[0] + 1
                                         == other_workspace.info.coordinates[0]
                                 {
@@ -732,8 +1362,26 @@ impl Application for App {
                                 }
                             }
                         }
-                        Some(DropTarget::OutputToplevels(_, _) | DropTarget::WorkspacesBar(_))
-                        | None => {}
+                        Some(DropTarget::WorkspacesBar(output)) => {
+                            if let Some(workspace) = self.workspaces.for_handle(handle) {
+                                let last_workspace = self.workspaces.for_output(&output).last();
+                                if !workspace.outputs.contains(&output) && workspace.can_assign() {
+                                    self.send_wayland_cmd(backend::Cmd::MoveWorkspaceToOutput(
+                                        handle.clone(),
+                                        output,
+                                        None,
+                                    ));
+                                } else if let Some(last_workspace) = last_workspace
+                                    && last_workspace.handle() != handle
+                                {
+                                    self.send_wayland_cmd(backend::Cmd::MoveWorkspaceAfter(
+                                        handle.clone(),
+                                        last_workspace.handle().clone(),
+                                    ));
+                                }
+                            }
+                        }
+                        Some(DropTarget::OutputToplevels(_, _)) | None => {}
                     }
                 }
             }
@@ -750,15 +1398,76 @@ impl Application for App {
                     workspace.has_cursor = entered;
                 }
             }
+            Msg::ToplevelPreviewSizes(output, sizes) => {
+                let scale = self
+                    .outputs
+                    .iter()
+                    .find(|o| o.handle == output)
+                    .map_or(1, |o| o.scale.max(1)) as f32;
+                let mut changed = false;
+                for (handle, size) in sizes {
+                    let size = (
+                        (size.width * scale).ceil() as u32,
+                        (size.height * scale).ceil() as u32,
+                    );
+                    changed |= self.preview_sizes.insert(handle, size) != Some(size);
+                }
+                if changed {
+                    self.update_capture_filter();
+                }
+            }
+            Msg::EnteredToplevelPreview(toplevel_handle, entered) => {
+                if entered {
+                    self.hovered_toplevel = Some(toplevel_handle);
+                } else if self.hovered_toplevel.as_ref() == Some(&toplevel_handle) {
+                    self.hovered_toplevel = None;
+                }
+                self.update_active_toplevels();
+            }
             Msg::DbusInterface(interface) => {
                 if let Ok(interface) = interface {
+                    interface.set_visible(self.visible);
                     self.dbus_interface = Some(interface);
+                    self.update_dbus_outputs();
                 }
             }
             Msg::DBus(evt) => {
                 return match evt {
                     dbus::Event::Show => self.show(),
                     dbus::Event::Hide => self.hide(),
+                    dbus::Event::Toggle => self.toggle(),
+                    dbus::Event::ShowOnOutput(name) => {
+                        // Validated by the D-Bus method, but may have been removed since
+                        if !self.outputs.iter().any(|o| o.name == name) {
+                            return Task::none();
+                        }
+                        self.show_on_output(Some(name))
+                    }
+                    dbus::Event::ShowMode(mode) => {
+                        // Keep the outputs already shown on
+                        let task = if self.visible {
+                            Task::none()
+                        } else {
+                            self.show()
+                        };
+                        // Typing no longer filters toplevels
+                        if self.show_mode == dbus::ShowMode::Search
+                            && mode == dbus::ShowMode::Default
+                            && self.conf.config.action_on_typing != ActionOnTyping::FilterWindows
+                        {
+                            self.search.clear();
+                        }
+                        self.show_mode = mode;
+                        task
+                    }
+                    dbus::Event::BeginGesture => self.begin_gesture(),
+                    dbus::Event::UpdateGesture(progress) => {
+                        if let Some(transition) = &mut self.transition {
+                            transition.update_gesture(progress as f32);
+                        }
+                        Task::none()
+                    }
+                    dbus::Event::EndGesture(commit) => self.end_gesture(commit),
                 };
             }
             Msg::PanelContainerEntries(entries) => {
@@ -771,6 +1480,17 @@ impl Application for App {
                 self.panel_configs.insert(config.name.clone(), Some(config));
             }
             Msg::ActionOnTyping(input) => {
+                // Typed into the rename text input
+                if self.renaming.is_some() {
+                    return Task::none();
+                }
+                if self.conf.config.action_on_typing == ActionOnTyping::FilterWindows
+                    || self.show_mode == dbus::ShowMode::Search
+                {
+                    self.search.push_str(&input);
+                    self.focus_top_search_match();
+                    return Task::none();
+                }
                 let cmd = match self.conf.workspace_config.action_on_typing {
                     cosmic_comp_config::workspace::Action::None => return Task::none(),
                     cosmic_comp_config::workspace::Action::OpenLauncher => {
@@ -795,16 +1515,136 @@ impl Application for App {
                 }
                 self.action_on_typing_activated = true;
             }
+            Msg::SearchBackspace => {
+                if self.search.pop().is_some() {
+                    self.focus_top_search_match();
+                }
+            }
+            Msg::Escape => {
+                // Close menu, cancel rename, or clear search first, if there is one
+                if self.context_menu.is_some() {
+                    self.context_menu = None;
+                } else if self.renaming.is_some() {
+                    self.renaming = None;
+                } else if !self.search.is_empty() {
+                    self.search.clear();
+                    self.focus = None;
+                    self.update_active_toplevels();
+                } else {
+                    return self.hide();
+                }
+            }
+            Msg::ToplevelContextMenu(handle) => {
+                self.context_menu = handle;
+            }
+            Msg::ToplevelAction(handle, action) => {
+                self.context_menu = None;
+                let cmd = match action {
+                    ToplevelAction::Minimize(value) => {
+                        backend::Cmd::SetToplevelMinimized(handle, value)
+                    }
+                    ToplevelAction::Maximize(value) => {
+                        backend::Cmd::SetToplevelMaximized(handle, value)
+                    }
+                    ToplevelAction::Fullscreen(value) => {
+                        backend::Cmd::SetToplevelFullscreen(handle, value)
+                    }
+                    ToplevelAction::Sticky(value) => backend::Cmd::SetToplevelSticky(handle, value),
+                    ToplevelAction::MoveToWorkspace(workspace, output) => {
+                        backend::Cmd::MoveToplevelToWorkspace(handle, workspace, output)
+                    }
+                    ToplevelAction::MoveToOutput(output) => {
+                        let Some(workspace) =
+                            self.workspaces.for_output(&output).find(|w| w.is_active())
+                        else {
+                            return Task::none();
+                        };
+                        backend::Cmd::MoveToplevelToWorkspace(
+                            handle,
+                            workspace.handle().clone(),
+                            output,
+                        )
+                    }
+                };
+                self.send_wayland_cmd(cmd);
+            }
+            Msg::SetFocus(focus) => {
+                self.focus = focus;
+                self.update_active_toplevels();
+            }
+            Msg::FocusNext(surface_id, reverse) => {
+                if let Some(surface) = self.layer_surfaces.get(&surface_id) {
+                    let output = surface.output.clone();
+                    self.focus_next(&output, reverse);
+                }
+            }
+            Msg::ActivateFocused => match self.focus.clone() {
+                Some(Focus::Toplevel(handle)) => {
+                    return self.update(Msg::ActivateToplevel(handle));
+                }
+                Some(Focus::Workspace(handle)) => {
+                    return self.update(Msg::ActivateWorkspace(handle));
+                }
+                None => {}
+            },
+            Msg::CloseFocused => {
+                if let Some(Focus::Toplevel(handle)) = self.focus.clone() {
+                    return self.update(Msg::CloseToplevel(handle));
+                }
+            }
+            Msg::StartRename(handle) => {
+                if let Some(workspace) = self.workspaces.for_handle(&handle) {
+                    let name = workspace.rename_name(&self.conf.config.workspace_names);
+                    self.renaming = Some((handle, name));
+                    return Task::batch([
+                        cosmic::widget::text_input::focus(view::RENAME_INPUT_ID.clone()),
+                        cosmic::widget::text_input::select_all(view::RENAME_INPUT_ID.clone()),
+                    ]);
+                }
+            }
+            Msg::RenameFocused => {
+                // Focused workspace, or otherwise the one under the cursor
+                let handle = match &self.focus {
+                    Some(Focus::Workspace(handle)) => Some(handle.clone()),
+                    _ => self
+                        .workspaces
+                        .0
+                        .iter()
+                        .find(|w| w.has_cursor)
+                        .map(|w| w.handle().clone()),
+                };
+                if let Some(handle) = handle {
+                    return self.update(Msg::StartRename(handle));
+                }
+            }
+            Msg::RenameInput(name) => {
+                if let Some((_, renaming_name)) = &mut self.renaming {
+                    *renaming_name = name;
+                }
+            }
+            Msg::SubmitRename => {
+                if let Some((handle, name)) = self.renaming.take() {
+                    self.rename_workspace(&handle, name.trim());
+                }
+            }
             Msg::Ignore => {}
         }
 
         Task::none()
     }
     fn dbus_activation(&mut self, msg: dbus_activation::Message) -> Task<cosmic::Action<Msg>> {
-        if let dbus_activation::Details::Activate = msg.msg {
-            self.toggle()
-        } else {
-            Task::none()
+        match msg.msg {
+            dbus_activation::Details::Activate => self.toggle(),
+            dbus_activation::Details::ActivateAction { action, .. } => {
+                match action.parse::<WorkspaceCommands>() {
+                    Ok(cmd) => self.run_command(cmd),
+                    Err(()) => {
+                        log::warn!("Unrecognized activation action '{}'", action);
+                        Task::none()
+                    }
+                }
+            }
+            _ => Task::none(),
         }
     }
 
@@ -813,7 +1653,7 @@ impl Application for App {
     }
 
     fn subscription(&self) -> Subscription<Msg> {
-        let events = iced::event::listen_with(|evt, _, _| match evt {
+        let events = iced::event::listen_with(|evt, status, id| match evt {
             iced::Event::PlatformSpecific(iced::event::PlatformSpecific::Wayland(evt)) => {
                 if !matches!(evt, WaylandEvent::RequestResize) {
                     Some(Msg::WaylandEvent(evt))
@@ -824,7 +1664,27 @@ impl Application for App {
             iced::Event::Keyboard(iced::keyboard::Event::KeyPressed {
                 key: Key::Named(Named::Escape),
                 ..
-            }) => Some(Msg::Close),
+            }) => Some(Msg::Escape),
+            // Only handle navigation keys not already captured by a widget with
+            // a focused child
+            iced::Event::Keyboard(iced::keyboard::Event::KeyPressed {
+                key: Key::Named(named),
+                modifiers,
+                ..
+            }) if status == iced::event::Status::Ignored => match named {
+                Named::Tab => Some(Msg::FocusNext(id, modifiers.shift())),
+                // Nothing is focused yet, so focus the first or last item
+                Named::ArrowRight | Named::ArrowDown => Some(Msg::FocusNext(id, false)),
+                Named::ArrowLeft | Named::ArrowUp => Some(Msg::FocusNext(id, true)),
+                Named::Enter => Some(Msg::ActivateFocused),
+                Named::Delete => Some(Msg::CloseFocused),
+                Named::Backspace => Some(Msg::SearchBackspace),
+                Named::F2 => Some(Msg::RenameFocused),
+                Named::Space if !modifiers.control() && !modifiers.alt() && !modifiers.logo() => {
+                    Some(Msg::ActionOnTyping(" ".to_string()))
+                }
+                _ => None,
+            },
             iced::Event::Keyboard(iced::keyboard::Event::KeyPressed {
                 key: Key::Character(key),
                 modifiers,
@@ -841,8 +1701,8 @@ impl Application for App {
         });
         let config_subscription = cosmic_config::config_subscription::<_, CosmicWorkspacesConfig>(
             "config-sub",
-            "com.system76.CosmicWorkspaces".into(),
-            1,
+            Self::APP_ID.into(),
+            CONFIG_VERSION,
         )
         .map(|update| {
             if !update.errors.is_empty() {
@@ -881,12 +1741,16 @@ impl Application for App {
             bg_subscription,
         ];
         if let Some(conn) = self.conn.clone() {
-            subscriptions.push(backend::subscription(conn).map(Msg::Wayland));
+            subscriptions.push(backend::subscription(self.backend, conn).map(Msg::Wayland));
         }
         if let Some(interface) = &self.dbus_interface {
             subscriptions.push(interface.subscription().map(Msg::DBus));
         }
         subscriptions.push(panel_subscriptions(self.panel_configs.keys()));
+        // Gesture updates already cause a redraw
+        if self.transition.is_some_and(|t| !t.is_gesture()) {
+            subscriptions.push(iced::window::frames().map(|(_id, now)| Msg::AnimationFrame(now)));
+        }
         iced::Subscription::batch(subscriptions)
     }
 
@@ -0,0 +1,1044 @@
+//! Tests of `App::update`, without a Wayland compositor.
+//!
+//! `TestApp` feeds messages to an `App`, and records the commands it sends to
+//! the backend. Workspaces and toplevels use mock backend handles. Outputs are
+//! real proxies, but on a connection to a socket nothing is listening on.
+
+use cosmic::cctk::wayland_client::protocol::wl_registry;
+use cosmic::cctk::wayland_client::{EventQueue, QueueHandle, delegate_noop};
+use std::os::unix::net::UnixStream;
+
+use super::*;
+
+struct TestState;
+
+delegate_noop!(TestState: ignore wl_registry::WlRegistry);
+delegate_noop!(TestState: ignore wl_output::WlOutput);
+
+struct TestApp {
+    app: App,
+    cmd_loop: calloop::EventLoop<'static, Vec<backend::Cmd>>,
+    registry: wl_registry::WlRegistry,
+    qh: QueueHandle<TestState>,
+    next_output_name: u32,
+    _event_queue: EventQueue<TestState>,
+    _conn: Connection,
+    _server: UnixStream,
+}
+
+impl TestApp {
+    fn new() -> Self {
+        let (client, server) = UnixStream::pair().unwrap();
+        let conn = Connection::from_socket(client).unwrap();
+        let event_queue = conn.new_event_queue();
+        let qh = event_queue.handle();
+        let registry = conn.display().get_registry(&qh, ());
+
+        let mut app = App::default();
+        let (cmd_sender, cmd_channel) = calloop::channel::channel();
+        let _ = app.update(Msg::Wayland(backend::Event::CmdSender(cmd_sender)));
+        let _ = app.update(Msg::Wayland(backend::Event::ToplevelCapabilities(vec![
+            ToplevelCapability::Close,
+            ToplevelCapability::Activate,
+            ToplevelCapability::Maximize,
+            ToplevelCapability::Minimize,
+            ToplevelCapability::Fullscreen,
+            ToplevelCapability::MoveToWorkspace,
+            ToplevelCapability::Sticky,
+        ])));
+        let cmd_loop = calloop::EventLoop::try_new().unwrap();
+        cmd_loop
+            .handle()
+            .insert_source(cmd_channel, |event, (), cmds: &mut Vec<backend::Cmd>| {
+                if let calloop::channel::Event::Msg(cmd) = event {
+                    cmds.push(cmd);
+                }
+            })
+            .unwrap();
+
+        Self {
+            app,
+            cmd_loop,
+            registry,
+            qh,
+            next_output_name: 1,
+            _event_queue: event_queue,
+            _conn: conn,
+            _server: server,
+        }
+    }
+
+    fn update(&mut self, msg: Msg) {
+        // Tasks need an iced runtime; only the state change and commands are tested
+        let _ = self.app.update(msg);
+    }
+
+    /// Commands sent to the backend since last called
+    fn take_cmds(&mut self) -> Vec<backend::Cmd> {
+        let mut cmds = Vec::new();
+        self.cmd_loop
+            .dispatch(Some(Duration::ZERO), &mut cmds)
+            .unwrap();
+        cmds
+    }
+
+    fn add_output(&mut self, name: &str) -> wl_output::WlOutput {
+        let handle =
+            self.registry
+                .bind::<wl_output::WlOutput, _, _>(self.next_output_name, 4, &self.qh, ());
+        self.next_output_name += 1;
+        self.app.outputs.push(Output {
+            handle: handle.clone(),
+            name: name.to_string(),
+            width: 1920,
+            height: 1080,
+            scale: 1,
+        });
+        handle
+    }
+
+    /// Send `Event::Workspaces` with `count` workspaces on `output`, the first of
+    /// which is active.
+    fn add_workspaces(&mut self, output: &wl_output::WlOutput, count: u32) -> Vec<WorkspaceHandle> {
+        let mut workspaces = self
+            .app
+            .workspaces
+            .0
+            .iter()
+            .map(|w| (w.outputs.clone(), w.info.clone()))
+            .collect::<Vec<_>>();
+        let handles = (0..count)
+            .map(|_| WorkspaceHandle::new_mock())
+            .collect::<Vec<_>>();
+        for (i, handle) in handles.iter().enumerate() {
+            workspaces.push((
+                HashSet::from([output.clone()]),
+                workspace(handle.clone(), i as u32, i == 0),
+            ));
+        }
+        self.update(Msg::Wayland(backend::Event::Workspaces(workspaces)));
+        handles
+    }
+
+    fn add_toplevel(
+        &mut self,
+        output: &wl_output::WlOutput,
+        workspace: &WorkspaceHandle,
+    ) -> ToplevelHandle {
+        let handle = ToplevelHandle::new_mock();
+        let info = ToplevelInfo {
+            title: "Test".to_string(),
+            app_id: "com.example.test".to_string(),
+            output: HashSet::from([output.clone()]),
+            workspace: HashSet::from([workspace.clone()]),
+            ..Default::default()
+        };
+        self.update(Msg::Wayland(backend::Event::NewToplevel(
+            handle.clone(),
+            info,
+            false,
+        )));
+        handle
+    }
+}
+
+fn workspace(handle: WorkspaceHandle, coordinate: u32, active: bool) -> backend::Workspace {
+    backend::Workspace {
+        handle,
+        name: format!("Workspace {coordinate}"),
+        coordinates: vec![coordinate],
+        state: if active {
+            ext_workspace_handle_v1::State::Active
+        } else {
+            ext_workspace_handle_v1::State::empty()
+        },
+        capabilities: ext_workspace_handle_v1::WorkspaceCapabilities::Activate,
+        cosmic_capabilities: zcosmic_workspace_handle_v2::WorkspaceCapabilities::empty(),
+        cosmic_state: zcosmic_workspace_handle_v2::State::empty(),
+    }
+}
+
+fn scroll(lines: f32) -> ScrollDelta {
+    ScrollDelta::Lines { x: 0., y: lines }
+}
+
+#[test]
+fn workspaces_sorted_by_coordinates() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let handles = (0..3)
+        .map(|_| WorkspaceHandle::new_mock())
+        .collect::<Vec<_>>();
+    let workspaces = vec![
+        (
+            HashSet::from([output.clone()]),
+            workspace(handles[2].clone(), 2, false),
+        ),
+        (
+            HashSet::from([output.clone()]),
+            workspace(handles[0].clone(), 0, true),
+        ),
+        (
+            HashSet::from([output.clone()]),
+            workspace(handles[1].clone(), 1, false),
+        ),
+    ];
+    t.update(Msg::Wayland(backend::Event::Workspaces(workspaces)));
+    let order = t
+        .app
+        .workspaces
+        .0
+        .iter()
+        .map(|w| w.handle().clone())
+        .collect::<Vec<_>>();
+    assert_eq!(order, handles);
+}
+
+#[test]
+fn workspaces_update_keeps_state() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let handles = t.add_workspaces(&output, 3);
+    t.update(Msg::EnteredWorkspaceSidebarEntry(handles[0].clone(), true));
+    let dnd_source_id = t.app.workspaces.0[0].dnd_source_id.clone();
+
+    // Second workspace removed, and third renamed
+    let mut renamed = workspace(handles[2].clone(), 1, false);
+    renamed.name = "Renamed".to_string();
+    let workspaces = vec![
+        (
+            HashSet::from([output.clone()]),
+            workspace(handles[0].clone(), 0, true),
+        ),
+        (HashSet::from([output.clone()]), renamed),
+    ];
+    t.update(Msg::Wayland(backend::Event::Workspaces(workspaces)));
+    assert_eq!(t.app.workspaces.0.len(), 2);
+    assert!(t.app.workspaces.for_handle(&handles[1]).is_none());
+    assert_eq!(
+        t.app.workspaces.for_handle(&handles[2]).unwrap().info.name,
+        "Renamed"
+    );
+    let workspace = t.app.workspaces.for_handle(&handles[0]).unwrap();
+    assert!(workspace.has_cursor);
+    assert_eq!(workspace.dnd_source_id, dnd_source_id);
+}
+
+#[test]
+fn capture_filter_follows_visibility() {
+    let mut t = TestApp::new();
+    let output1 = t.add_output("DP-1");
+    let output2 = t.add_output("DP-2");
+    let workspaces1 = t.add_workspaces(&output1, 2);
+    let workspaces2 = t.add_workspaces(&output2, 2);
+    // Nothing captured while hidden
+    assert_eq!(
+        t.take_cmds(),
+        vec![
+            backend::Cmd::CaptureFilter(backend::CaptureFilter::default()),
+            backend::Cmd::CaptureFilter(backend::CaptureFilter::default()),
+        ]
+    );
+
+    t.update(Msg::DBus(dbus::Event::Show));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::CaptureFilter(backend::CaptureFilter {
+            workspaces_on_outputs: vec![output1.clone(), output2.clone()],
+            toplevels_on_workspaces: vec![workspaces1[0].clone(), workspaces2[0].clone()],
+            workspace_size: Some(view::WORKSPACE_PREVIEW_MIN_SIZE),
+            ..Default::default()
+        })]
+    );
+
+    // Previews are captured until hiding has been animated
+    t.update(Msg::DBus(dbus::Event::Hide));
+    assert_eq!(t.take_cmds(), Vec::new());
+    t.update(Msg::AnimationFrame(Instant::now() + animation::DURATION));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::CaptureFilter(
+            backend::CaptureFilter::default()
+        )]
+    );
+
+    // Only one output
+    t.update(Msg::DBus(dbus::Event::ShowOnOutput("DP-2".to_string())));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::CaptureFilter(backend::CaptureFilter {
+            workspaces_on_outputs: vec![output2.clone()],
+            toplevels_on_workspaces: vec![workspaces2[0].clone()],
+            workspace_size: Some(view::WORKSPACE_PREVIEW_MIN_SIZE),
+            ..Default::default()
+        })]
+    );
+
+    // Moved to another output while shown
+    t.update(Msg::DBus(dbus::Event::ShowOnOutput("DP-1".to_string())));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::CaptureFilter(backend::CaptureFilter {
+            workspaces_on_outputs: vec![output1.clone()],
+            toplevels_on_workspaces: vec![workspaces1[0].clone()],
+            workspace_size: Some(view::WORKSPACE_PREVIEW_MIN_SIZE),
+            ..Default::default()
+        })]
+    );
+
+    // Shown on all outputs again
+    t.update(Msg::DBus(dbus::Event::Show));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::CaptureFilter(backend::CaptureFilter {
+            workspaces_on_outputs: vec![output1.clone(), output2.clone()],
+            toplevels_on_workspaces: vec![workspaces1[0].clone(), workspaces2[0].clone()],
+            workspace_size: Some(view::WORKSPACE_PREVIEW_MIN_SIZE),
+            ..Default::default()
+        })]
+    );
+}
+
+#[test]
+fn capture_filter_follows_active_workspace() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let handles = t.add_workspaces(&output, 2);
+    t.update(Msg::DBus(dbus::Event::Show));
+    t.take_cmds();
+
+    let workspaces = vec![
+        (
+            HashSet::from([output.clone()]),
+            workspace(handles[0].clone(), 0, false),
+        ),
+        (
+            HashSet::from([output.clone()]),
+            workspace(handles[1].clone(), 1, true),
+        ),
+    ];
+    t.update(Msg::Wayland(backend::Event::Workspaces(workspaces)));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::CaptureFilter(backend::CaptureFilter {
+            workspaces_on_outputs: vec![output.clone()],
+            toplevels_on_workspaces: vec![handles[1].clone()],
+            workspace_size: Some(view::WORKSPACE_PREVIEW_MIN_SIZE),
+            ..Default::default()
+        })]
+    );
+}
+
+#[test]
+fn toplevel_drop_on_workspace() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 2);
+    let toplevel = t.add_toplevel(&output, &workspaces[0]);
+    t.take_cmds();
+
+    t.update(Msg::StartDrag(DragSurface::Toplevel(toplevel.clone())));
+    t.update(Msg::DndEnter(
+        DropTarget::WorkspaceSidebarEntry(workspaces[1].clone(), output.clone()),
+        0.,
+        0.,
+        Vec::new(),
+    ));
+    t.update(Msg::DndToplevelDrop(DragToplevel {}));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::MoveToplevelToWorkspace(
+            toplevel,
+            workspaces[1].clone(),
+            output
+        )]
+    );
+    assert_eq!(t.app.drop_target, None);
+}
+
+#[test]
+fn toplevel_actions_need_capabilities() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 2);
+    let toplevel = t.add_toplevel(&output, &workspaces[0]);
+    t.update(Msg::DBus(dbus::Event::Show));
+    t.update(Msg::Wayland(backend::Event::ToplevelCapabilities(
+        Vec::new(),
+    )));
+    t.take_cmds();
+
+    t.update(Msg::CloseToplevel(toplevel.clone()));
+    t.update(Msg::ActivateToplevel(toplevel.clone()));
+    t.update(Msg::StartDrag(DragSurface::Toplevel(toplevel.clone())));
+    t.update(Msg::DndEnter(
+        DropTarget::WorkspaceSidebarEntry(workspaces[1].clone(), output.clone()),
+        0.,
+        0.,
+        Vec::new(),
+    ));
+    t.update(Msg::DndToplevelDrop(DragToplevel {}));
+    assert_eq!(t.take_cmds(), Vec::new());
+    assert_eq!(t.app.drop_target, None);
+    assert!(t.app.visible);
+}
+
+#[test]
+fn toplevel_drop_without_target() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 2);
+    let toplevel = t.add_toplevel(&output, &workspaces[0]);
+    t.take_cmds();
+
+    let target = DropTarget::WorkspaceSidebarEntry(workspaces[1].clone(), output.clone());
+    t.update(Msg::StartDrag(DragSurface::Toplevel(toplevel)));
+    t.update(Msg::DndEnter(target.clone(), 0., 0., Vec::new()));
+    t.update(Msg::DndLeave(target));
+    t.update(Msg::DndToplevelDrop(DragToplevel {}));
+    assert_eq!(t.take_cmds(), Vec::new());
+}
+
+#[test]
+fn toplevel_drop_on_workspaces_bar() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 2);
+    let toplevel = t.add_toplevel(&output, &workspaces[0]);
+    t.update(Msg::Wayland(backend::Event::WorkspaceGroups(vec![
+        backend::WorkspaceGroup {
+            outputs: HashSet::from([output.clone()]),
+            capabilities: ext_workspace_group_handle_v1::GroupCapabilities::CreateWorkspace,
+        },
+    ])));
+    t.take_cmds();
+
+    let drop_on_bar = |t: &mut TestApp| {
+        t.update(Msg::StartDrag(DragSurface::Toplevel(toplevel.clone())));
+        t.update(Msg::DndEnter(
+            DropTarget::WorkspacesBar(output.clone()),
+            0.,
+            0.,
+            Vec::new(),
+        ));
+        t.update(Msg::DndToplevelDrop(DragToplevel {}));
+        t.take_cmds()
+    };
+
+    // Last workspace is empty, so it's used instead of creating one
+    assert_eq!(
+        drop_on_bar(&mut t),
+        vec![backend::Cmd::MoveToplevelToWorkspace(
+            toplevel.clone(),
+            workspaces[1].clone(),
+            output.clone()
+        )]
+    );
+
+    t.add_toplevel(&output, &workspaces[1]);
+    t.take_cmds();
+    assert_eq!(
+        drop_on_bar(&mut t),
+        vec![backend::Cmd::MoveToplevelToNewWorkspace(
+            toplevel.clone(),
+            output.clone(),
+            "3".to_string()
+        )]
+    );
+}
+
+#[test]
+fn workspace_drop_reorders() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 3);
+    t.take_cmds();
+
+    t.update(Msg::StartDrag(DragSurface::Workspace(
+        workspaces[2].clone(),
+    )));
+    t.update(Msg::DndEnter(
+        DropTarget::WorkspaceSidebarEntry(workspaces[0].clone(), output.clone()),
+        0.,
+        0.,
+        Vec::new(),
+    ));
+    t.update(Msg::DndWorkspaceDrop(DragWorkspace {}));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::MoveWorkspaceBefore(
+            workspaces[2].clone(),
+            workspaces[0].clone()
+        )]
+    );
+}
+
+#[test]
+fn workspace_drop_on_other_output() {
+    let mut t = TestApp::new();
+    let output1 = t.add_output("DP-1");
+    let output2 = t.add_output("DP-2");
+    let workspaces1 = t.add_workspaces(&output1, 2);
+    let workspaces2 = t.add_workspaces(&output2, 2);
+    t.take_cmds();
+
+    let drop_on_output2 = |t: &mut TestApp| {
+        t.update(Msg::StartDrag(DragSurface::Workspace(
+            workspaces1[1].clone(),
+        )));
+        t.update(Msg::DndEnter(
+            DropTarget::WorkspaceSidebarEntry(workspaces2[1].clone(), output2.clone()),
+            0.,
+            0.,
+            Vec::new(),
+        ));
+        t.update(Msg::DndWorkspaceDrop(DragWorkspace {}));
+        t.take_cmds()
+    };
+
+    // Without the assign capability, fall back to moving before the workspace
+    assert_eq!(
+        drop_on_output2(&mut t),
+        vec![backend::Cmd::MoveWorkspaceBefore(
+            workspaces1[1].clone(),
+            workspaces2[1].clone()
+        )]
+    );
+
+    t.app
+        .workspaces
+        .for_handle_mut(&workspaces1[1])
+        .unwrap()
+        .info
+        .capabilities |= ext_workspace_handle_v1::WorkspaceCapabilities::Assign;
+    assert_eq!(
+        drop_on_output2(&mut t),
+        vec![backend::Cmd::MoveWorkspaceToOutput(
+            workspaces1[1].clone(),
+            output2.clone(),
+            Some(workspaces2[1].clone())
+        )]
+    );
+}
+
+#[test]
+fn workspace_drop_on_workspaces_bar() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 3);
+    t.take_cmds();
+
+    let drop_on_bar = |t: &mut TestApp, workspace: &WorkspaceHandle| {
+        t.update(Msg::StartDrag(DragSurface::Workspace(workspace.clone())));
+        t.update(Msg::DndEnter(
+            DropTarget::WorkspacesBar(output.clone()),
+            0.,
+            0.,
+            Vec::new(),
+        ));
+        t.update(Msg::DndWorkspaceDrop(DragWorkspace {}));
+        t.take_cmds()
+    };
+
+    assert_eq!(
+        drop_on_bar(&mut t, &workspaces[0]),
+        vec![backend::Cmd::MoveWorkspaceAfter(
+            workspaces[0].clone(),
+            workspaces[2].clone()
+        )]
+    );
+    // Already at the end
+    assert_eq!(drop_on_bar(&mut t, &workspaces[2]), Vec::new());
+}
+
+#[test]
+fn workspace_drop_in_same_position() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 3);
+    t.take_cmds();
+
+    // Dropping before the next workspace doesn't move it
+    t.update(Msg::StartDrag(DragSurface::Workspace(
+        workspaces[0].clone(),
+    )));
+    t.update(Msg::DndEnter(
+        DropTarget::WorkspaceSidebarDragPlaceholder(workspaces[1].clone(), output.clone()),
+        0.,
+        0.,
+        Vec::new(),
+    ));
+    t.update(Msg::DndWorkspaceDrop(DragWorkspace {}));
+    assert_eq!(t.take_cmds(), Vec::new());
+}
+
+#[test]
+fn scroll_wraps_around() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 3);
+    t.take_cmds();
+
+    // Scrolling up from the first workspace wraps to the last
+    t.update(Msg::OnScroll(output.clone(), scroll(1.)));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::ActivateWorkspace(workspaces[2].clone())]
+    );
+
+    // Scrolling down from the last workspace wraps to the first
+    let last_active = vec![
+        (
+            HashSet::from([output.clone()]),
+            workspace(workspaces[0].clone(), 0, false),
+        ),
+        (
+            HashSet::from([output.clone()]),
+            workspace(workspaces[1].clone(), 1, false),
+        ),
+        (
+            HashSet::from([output.clone()]),
+            workspace(workspaces[2].clone(), 2, true),
+        ),
+    ];
+    t.update(Msg::Wayland(backend::Event::Workspaces(last_active)));
+    t.take_cmds();
+    t.update(Msg::OnScroll(output.clone(), scroll(-1.)));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::ActivateWorkspace(workspaces[0].clone())]
+    );
+}
+
+#[test]
+fn rename_with_compositor() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let handle = WorkspaceHandle::new_mock();
+    let mut info = workspace(handle.clone(), 0, true);
+    info.cosmic_capabilities = zcosmic_workspace_handle_v2::WorkspaceCapabilities::Rename;
+    t.update(Msg::Wayland(backend::Event::Workspaces(vec![(
+        HashSet::from([output.clone()]),
+        info,
+    )])));
+    t.take_cmds();
+
+    t.update(Msg::SetFocus(Some(Focus::Workspace(handle.clone()))));
+    t.update(Msg::RenameFocused);
+    assert_eq!(
+        t.app.renaming,
+        Some((handle.clone(), "Workspace 0".to_string()))
+    );
+    t.update(Msg::RenameInput(" Code ".to_string()));
+    t.update(Msg::SubmitRename);
+    assert_eq!(t.app.renaming, None);
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::RenameWorkspace(handle, "Code".to_string())]
+    );
+}
+
+#[test]
+fn workspace_display_name() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let handle = WorkspaceHandle::new_mock();
+    let mut info = workspace(handle.clone(), 0, true);
+    info.name = "1".to_string();
+    t.update(Msg::Wayland(backend::Event::Workspaces(vec![(
+        HashSet::from([output.clone()]),
+        info,
+    )])));
+    let workspace = t.app.workspaces.for_handle(&handle).unwrap();
+
+    let no_names = BTreeMap::new();
+    assert_eq!(
+        workspace.display_name(&no_names),
+        fl!("workspace", number = "1")
+    );
+    assert_eq!(workspace.rename_name(&no_names), "1");
+
+    // Compositor doesn't support renaming, so the local name is used
+    let names = BTreeMap::from([(vec![0], "Code".to_string())]);
+    assert_eq!(workspace.display_name(&names), "Code");
+    assert_eq!(workspace.rename_name(&names), "Code");
+}
+
+#[test]
+fn escape_cancels_rename() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 1);
+    t.update(Msg::DBus(dbus::Event::Show));
+    t.update(Msg::StartRename(workspaces[0].clone()));
+    t.update(Msg::Escape);
+    assert_eq!(t.app.renaming, None);
+    assert!(t.app.visible);
+}
+
+#[test]
+fn toplevel_move_to_output() {
+    let mut t = TestApp::new();
+    let output1 = t.add_output("DP-1");
+    let output2 = t.add_output("DP-2");
+    let workspaces1 = t.add_workspaces(&output1, 1);
+    let workspaces2 = t.add_workspaces(&output2, 2);
+    let toplevel = t.add_toplevel(&output1, &workspaces1[0]);
+    t.take_cmds();
+
+    t.update(Msg::ToplevelContextMenu(Some(toplevel.clone())));
+    t.update(Msg::ToplevelAction(
+        toplevel.clone(),
+        ToplevelAction::MoveToOutput(output2.clone()),
+    ));
+    // Moved to active workspace on the output, and menu closed
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::MoveToplevelToWorkspace(
+            toplevel,
+            workspaces2[0].clone(),
+            output2
+        )]
+    );
+    assert_eq!(t.app.context_menu, None);
+}
+
+#[test]
+fn create_workspace_needs_group_capability() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    t.add_workspaces(&output, 2);
+    t.take_cmds();
+
+    t.update(Msg::NewWorkspace(output.clone()));
+    assert_eq!(t.take_cmds(), Vec::new());
+
+    t.update(Msg::Wayland(backend::Event::WorkspaceGroups(vec![
+        backend::WorkspaceGroup {
+            outputs: HashSet::from([output.clone()]),
+            capabilities: ext_workspace_group_handle_v1::GroupCapabilities::CreateWorkspace,
+        },
+    ])));
+    t.update(Msg::NewWorkspace(output.clone()));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::CreateWorkspace(output, "3".to_string())]
+    );
+}
+
+#[test]
+fn new_workspace_name_is_unused() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    t.add_workspaces(&output, 2);
+    t.update(Msg::Wayland(backend::Event::WorkspaceGroups(vec![
+        backend::WorkspaceGroup {
+            outputs: HashSet::from([output.clone()]),
+            capabilities: ext_workspace_group_handle_v1::GroupCapabilities::CreateWorkspace,
+        },
+    ])));
+    t.app.workspaces.0[1].info.name = "3".to_string();
+    t.take_cmds();
+
+    t.update(Msg::NewWorkspace(output.clone()));
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::CreateWorkspace(output, "4".to_string())]
+    );
+}
+
+#[test]
+fn remove_workspace_needs_capability() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let handle = WorkspaceHandle::new_mock();
+    let mut info = workspace(handle.clone(), 1, false);
+    let workspaces = t.add_workspaces(&output, 1);
+    t.take_cmds();
+
+    // Workspace from `add_workspaces` can't be removed
+    t.update(Msg::CloseWorkspace(workspaces[0].clone()));
+    assert_eq!(t.take_cmds(), Vec::new());
+
+    info.capabilities |= ext_workspace_handle_v1::WorkspaceCapabilities::Remove;
+    t.update(Msg::Wayland(backend::Event::Workspaces(vec![
+        (
+            HashSet::from([output.clone()]),
+            workspace(workspaces[0].clone(), 0, true),
+        ),
+        (HashSet::from([output.clone()]), info),
+    ])));
+    t.take_cmds();
+    t.update(Msg::CloseWorkspace(handle.clone()));
+    assert_eq!(t.take_cmds(), vec![backend::Cmd::RemoveWorkspace(handle)]);
+}
+
+#[test]
+fn hide_animates_before_destroying_surfaces() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    t.add_workspaces(&output, 1);
+    t.update(Msg::DBus(dbus::Event::Show));
+    assert!(t.app.transition.is_some_and(|t| t.opening));
+    assert_eq!(t.app.layer_surfaces.len(), 1);
+
+    t.update(Msg::Close);
+    assert!(!t.app.visible);
+    assert!(t.app.transition.is_some_and(|t| !t.opening));
+    assert_eq!(t.app.layer_surfaces.len(), 1);
+
+    // Showing again before the transition finishes reuses the surface
+    t.update(Msg::DBus(dbus::Event::Show));
+    assert!(t.app.transition.is_some_and(|t| t.opening));
+    assert_eq!(t.app.layer_surfaces.len(), 1);
+
+    t.update(Msg::AnimationFrame(Instant::now() + animation::DURATION));
+    assert_eq!(t.app.transition, None);
+    assert!(t.app.visible);
+}
+
+#[test]
+fn spawned_toplevel_hides_overview() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 1);
+    t.update(Msg::DBus(dbus::Event::Show));
+
+    let info = ToplevelInfo {
+        output: HashSet::from([output.clone()]),
+        workspace: HashSet::from([workspaces[0].clone()]),
+        ..Default::default()
+    };
+    t.update(Msg::Wayland(backend::Event::NewToplevel(
+        ToplevelHandle::new_mock(),
+        info.clone(),
+        true,
+    )));
+    assert!(t.app.visible);
+
+    t.update(Msg::Wayland(backend::Event::NewToplevel(
+        ToplevelHandle::new_mock(),
+        info,
+        false,
+    )));
+    assert!(!t.app.visible);
+}
+
+#[test]
+fn reduced_motion_disables_transition() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    t.add_workspaces(&output, 1);
+    t.app.conf.config.reduced_motion = true;
+    t.update(Msg::DBus(dbus::Event::Show));
+    assert_eq!(t.app.transition, None);
+    t.update(Msg::Close);
+    assert_eq!(t.app.transition, None);
+}
+
+#[test]
+fn gesture_reveals_overview() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    t.add_workspaces(&output, 1);
+    t.update(Msg::DBus(dbus::Event::BeginGesture));
+    assert!(t.app.visible);
+    assert_eq!(t.app.transition_progress(), 0.);
+    t.update(Msg::DBus(dbus::Event::UpdateGesture(0.5)));
+    assert_eq!(t.app.transition_progress(), 0.5);
+
+    // Cancelling hides the overview again
+    t.update(Msg::DBus(dbus::Event::EndGesture(false)));
+    assert!(!t.app.visible);
+    assert!(
+        t.app
+            .transition
+            .is_some_and(|t| !t.opening && !t.is_gesture())
+    );
+
+    t.update(Msg::DBus(dbus::Event::BeginGesture));
+    t.update(Msg::DBus(dbus::Event::UpdateGesture(0.8)));
+    t.update(Msg::DBus(dbus::Event::EndGesture(true)));
+    assert!(t.app.visible);
+    assert!(
+        t.app
+            .transition
+            .is_some_and(|t| t.opening && !t.is_gesture())
+    );
+}
+
+#[test]
+fn gesture_hides_overview() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    t.add_workspaces(&output, 1);
+    t.update(Msg::DBus(dbus::Event::Show));
+    t.update(Msg::AnimationFrame(Instant::now() + animation::DURATION));
+    t.update(Msg::DBus(dbus::Event::BeginGesture));
+    t.update(Msg::DBus(dbus::Event::UpdateGesture(0.25)));
+    assert!(t.app.visible);
+    assert_eq!(t.app.transition_progress(), 0.75);
+    t.update(Msg::DBus(dbus::Event::EndGesture(true)));
+    assert!(!t.app.visible);
+}
+
+#[test]
+fn gesture_with_reduced_motion() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    t.add_workspaces(&output, 1);
+    t.app.conf.config.reduced_motion = true;
+    t.update(Msg::DBus(dbus::Event::BeginGesture));
+    t.update(Msg::DBus(dbus::Event::UpdateGesture(0.5)));
+    assert!(!t.app.visible);
+    t.update(Msg::DBus(dbus::Event::EndGesture(true)));
+    assert!(t.app.visible);
+    assert_eq!(t.app.transition, None);
+}
+
+#[test]
+fn capture_rate_follows_hover_and_focus() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 1);
+    let toplevel1 = t.add_toplevel(&output, &workspaces[0]);
+    let toplevel2 = t.add_toplevel(&output, &workspaces[0]);
+    t.update(Msg::DBus(dbus::Event::Show));
+    t.take_cmds();
+
+    let active_toplevels = |cmds: Vec<backend::Cmd>| match cmds.as_slice() {
+        [backend::Cmd::CaptureFilter(filter)] => filter.active_toplevels.clone(),
+        _ => panic!("expected capture filter, got {cmds:?}"),
+    };
+
+    t.update(Msg::EnteredToplevelPreview(toplevel1.clone(), true));
+    assert_eq!(active_toplevels(t.take_cmds()), vec![toplevel1.clone()]);
+
+    t.update(Msg::SetFocus(Some(Focus::Toplevel(toplevel2.clone()))));
+    assert_eq!(
+        active_toplevels(t.take_cmds()),
+        vec![toplevel1.clone(), toplevel2.clone()]
+    );
+
+    // Unchanged, so no new filter
+    t.update(Msg::SetFocus(Some(Focus::Toplevel(toplevel1.clone()))));
+    assert_eq!(active_toplevels(t.take_cmds()), vec![toplevel1.clone()]);
+    t.update(Msg::EnteredToplevelPreview(toplevel2.clone(), false));
+    assert_eq!(t.take_cmds(), Vec::new());
+
+    // Still focused
+    t.update(Msg::EnteredToplevelPreview(toplevel1.clone(), false));
+    assert_eq!(t.take_cmds(), Vec::new());
+    t.update(Msg::SetFocus(None));
+    assert_eq!(active_toplevels(t.take_cmds()), Vec::new());
+}
+
+#[test]
+fn capture_size_follows_preview_size() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    t.app.outputs[0].scale = 2;
+    let workspaces = t.add_workspaces(&output, 1);
+    let toplevel = t.add_toplevel(&output, &workspaces[0]);
+    t.update(Msg::DBus(dbus::Event::Show));
+    t.take_cmds();
+
+    let sizes = vec![(toplevel.clone(), Size::new(300., 200.5))];
+    t.update(Msg::ToplevelPreviewSizes(output.clone(), sizes.clone()));
+    let (width, height) = view::WORKSPACE_PREVIEW_MIN_SIZE;
+    assert_eq!(
+        t.take_cmds(),
+        vec![backend::Cmd::CaptureFilter(backend::CaptureFilter {
+            workspaces_on_outputs: vec![output.clone()],
+            toplevels_on_workspaces: vec![workspaces[0].clone()],
+            toplevel_sizes: vec![(toplevel.clone(), (600, 401))],
+            workspace_size: Some((width * 2, height * 2)),
+            ..Default::default()
+        })]
+    );
+
+    // Unchanged
+    t.update(Msg::ToplevelPreviewSizes(output.clone(), sizes));
+    assert_eq!(t.take_cmds(), Vec::new());
+}
+
+#[test]
+fn previews_kept_while_hidden() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 1);
+    let toplevel = t.add_toplevel(&output, &workspaces[0]);
+    t.update(Msg::DBus(dbus::Event::Show));
+    t.update(Msg::Wayland(backend::Event::WorkspaceCapture(
+        workspaces[0].clone(),
+        backend::CaptureImage::new_mock(),
+    )));
+    t.update(Msg::Wayland(backend::Event::ToplevelCapture(
+        toplevel.clone(),
+        backend::CaptureImage::new_mock(),
+    )));
+
+    t.update(Msg::Close);
+    t.update(Msg::AnimationFrame(Instant::now() + animation::DURATION));
+    assert_eq!(t.app.capture_filter, backend::CaptureFilter::default());
+    assert!(t.app.workspaces.0[0].img.is_some());
+    assert!(t.app.toplevels.0[0].img.is_some());
+}
+
+#[test]
+fn preview_cache_prefers_workspaces_then_active_toplevels() {
+    let mut t = TestApp::new();
+    let output = t.add_output("DP-1");
+    let workspaces = t.add_workspaces(&output, 3);
+    // Added first, so kept first if not ordered by workspace
+    let inactive_toplevel = t.add_toplevel(&output, &workspaces[0]);
+    let active_toplevel = t.add_toplevel(&output, &workspaces[0]);
+    t.update(Msg::DBus(dbus::Event::Show));
+
+    // Only four images fit
+    let image = || {
+        let mut image = backend::CaptureImage::new_mock();
+        image.width = 4096;
+        image.height = 2048;
+        image
+    };
+    assert_eq!(image().size_in_bytes(), PREVIEW_CACHE_BYTES / 4);
+    for workspace in &workspaces {
+        t.update(Msg::Wayland(backend::Event::WorkspaceCapture(
+            workspace.clone(),
+            image(),
+        )));
+    }
+    for toplevel in [&inactive_toplevel, &active_toplevel] {
+        t.update(Msg::Wayland(backend::Event::ToplevelCapture(
+            toplevel.clone(),
+            image(),
+        )));
+    }
+    // Moved to an inactive workspace after it was captured
+    let mut info = t
+        .app
+        .toplevels
+        .for_handle_mut(&inactive_toplevel)
+        .unwrap()
+        .info
+        .clone();
+    info.workspace = HashSet::from([workspaces[1].clone()]);
+    t.update(Msg::Wayland(backend::Event::UpdateToplevel(
+        inactive_toplevel.clone(),
+        info,
+    )));
+
+    t.update(Msg::Close);
+    t.update(Msg::AnimationFrame(Instant::now() + animation::DURATION));
+    assert!(t.app.workspaces.0.iter().all(|w| w.img.is_some()));
+    let has_img = |handle: &ToplevelHandle| {
+        t.app
+            .toplevels
+            .0
+            .iter()
+            .any(|t| t.handle == *handle && t.img.is_some())
+    };
+    assert!(has_img(&active_toplevel));
+    assert!(!has_img(&inactive_toplevel));
+}
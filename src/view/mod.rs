@@ -12,11 +12,20 @@ use cosmic::iced::widget::{column, row};
 use cosmic::iced::{self, Alignment, Border, Length};
 use cosmic::widget::{self, Widget};
 use cosmic_comp_config::workspace::WorkspaceLayout;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::LazyLock;
 
 use crate::backend::{self, CaptureImage};
 use crate::dnd::{Drag, DragSurface, DragToplevel, DragWorkspace, DropTarget};
-use crate::{App, LayerSurface, Msg, Toplevel, Workspace};
+use crate::widgets::{Direction, Navigation};
+use crate::{
+    App, Focus, LayerSurface, Msg, Toplevel, ToplevelAction, ToplevelCapability,
+    ToplevelLayoutConfig, Workspace,
+};
+
+/// Text input for workspace name, while renaming
+pub(crate) static RENAME_INPUT_ID: LazyLock<widget::Id> =
+    LazyLock::new(|| widget::Id::new("rename-workspace"));
 
 fn dnd_source_with_drag_surface<D: AsMimeTypes + Send + Clone + 'static>(
     drag_content: D,
@@ -89,6 +98,13 @@ pub(crate) fn layer_surface<'a>(
         .flat_map(|t| &t.info.workspace)
         .collect::<HashSet<_>>();
     let layout = app.conf.workspace_config.workspace_layout;
+    // TODO multiple active workspaces? Not currently supported by cosmic.
+    let first_active_workspace = app
+        .workspaces
+        .for_output(&surface.output)
+        .find(|w| w.is_active());
+    let first_toplevel = app.toplevels_for_output(&surface.output).next();
+    let transition = app.transition_progress();
     let sidebar = workspaces_sidebar(
         app.workspaces.for_output(&surface.output),
         &workspaces_with_toplevels,
@@ -96,27 +112,30 @@ pub(crate) fn layer_surface<'a>(
         layout,
         app.drop_target.as_ref(),
         drag_workspace,
+        app.focus.as_ref(),
+        first_toplevel.map(|t| t.handle.clone()),
+        &app.conf.config.workspace_names,
+        app.renaming.as_ref(),
+        app.can_create_workspace(&surface.output),
+        transition,
     );
     let toplevels = toplevel_previews(
-        app.toplevels.0.iter().filter(|i| {
-            if !i.info.output.contains(&surface.output) {
-                return false;
-            }
-
-            i.info.workspace.iter().any(|workspace| {
-                app.workspaces
-                    .for_handle(workspace)
-                    .is_some_and(|x| x.is_active())
-            })
-        }),
+        app.toplevels_for_output(&surface.output),
+        &surface.output,
+        PreviewActions::new(app),
         layout,
+        &app.conf.config.toplevel_layout,
+        transition,
+        !app.conf.config.reduced_motion,
         drag_toplevel,
+        app.focus.as_ref(),
+        first_active_workspace.map(|w| w.handle().clone()),
+        &app.search,
+        app.context_menu
+            .as_ref()
+            .and_then(|handle| app.toplevels.0.iter().find(|t| t.handle == *handle))
+            .and_then(|toplevel| Some((&toplevel.handle, toplevel_context_menu(app, toplevel)?))),
     );
-    // TODO multiple active workspaces? Not currently supported by cosmic.
-    let first_active_workspace = app
-        .workspaces
-        .for_output(&surface.output)
-        .find(|w| w.is_active());
     let toplevels = if let Some(workspace) = first_active_workspace {
         dnd_destination_for_target(
             DropTarget::OutputToplevels(workspace.handle().clone(), surface.output.clone()),
@@ -151,10 +170,10 @@ pub(crate) fn layer_surface<'a>(
         .into()
 }
 
-fn close_button(on_press: Msg) -> cosmic::Element<'static, Msg> {
+fn close_button(on_press: Option<Msg>) -> cosmic::Element<'static, Msg> {
     widget::button::custom(widget::icon::from_name("window-close-symbolic").size(16))
         .class(cosmic::theme::Button::Destructive)
-        .on_press(on_press)
+        .on_press_maybe(on_press)
         .into()
 }
 
@@ -207,6 +226,57 @@ fn pin_button(workspace: &Workspace) -> cosmic::Element<'static, Msg> {
     .into()
 }
 
+fn remove_workspace_button(
+    workspace: &Workspace,
+    can_remove: bool,
+) -> cosmic::Element<'static, Msg> {
+    crate::widgets::visibility_wrapper(
+        widget::button::custom(
+            widget::icon::from_name("window-close-symbolic")
+                .symbolic(true)
+                .size(16),
+        )
+        .padding([4, 8])
+        .class(cosmic::theme::Button::Destructive)
+        .on_press(Msg::CloseWorkspace(workspace.handle().clone())),
+        // Allocate the same space as the pin button, so the name stays centered
+        can_remove && workspace.has_cursor,
+    )
+    .into()
+}
+
+fn new_workspace_button(
+    output: &wl_output::WlOutput,
+    layout: WorkspaceLayout,
+) -> cosmic::Element<'static, Msg> {
+    let content = column![
+        widget::icon::from_name("list-add-symbolic")
+            .symbolic(true)
+            .size(32),
+        widget::text::body(fl!("new-workspace")),
+    ]
+    .spacing(4)
+    .align_x(Alignment::Center);
+    let button = widget::button::custom(
+        widget::container(content)
+            .center_x(Length::Fill)
+            .center_y(Length::Fill),
+    )
+    .class(cosmic::theme::Button::Custom {
+        active: Box::new(|_focused, theme| workspace_item_appearance(theme, false, false)),
+        disabled: Box::new(|theme| workspace_item_appearance(theme, false, false)),
+        hovered: Box::new(|_focused, theme| workspace_item_appearance(theme, false, true)),
+        pressed: Box::new(|_focused, theme| workspace_item_appearance(theme, false, true)),
+    })
+    .padding(8)
+    .on_press(Msg::NewWorkspace(output.clone()));
+    match layout {
+        WorkspaceLayout::Vertical => button.width(Length::Fill).height(Length::Fixed(80.0)),
+        WorkspaceLayout::Horizontal => button.width(Length::Fixed(160.0)).height(Length::Fill),
+    }
+    .into()
+}
+
 fn workspace_item_appearance(
     theme: &cosmic::Theme,
     is_active: bool,
@@ -229,12 +299,43 @@ fn workspace_item_appearance(
     appearance
 }
 
+/// Name of workspace, or a text input if `rename` is set to the name being entered
+fn workspace_name(
+    workspace: &Workspace,
+    name: String,
+    rename: Option<String>,
+) -> cosmic::Element<'static, Msg> {
+    if let Some(rename) = rename {
+        return widget::text_input("", rename)
+            .id(RENAME_INPUT_ID.clone())
+            .on_input(Msg::RenameInput)
+            .on_submit(|_| Msg::SubmitRename)
+            .into();
+    }
+    let text = widget::text::body(name)
+        .ellipsize(Ellipsize::Middle(EllipsizeHeightLimit::Lines(1)))
+        .apply(widget::container)
+        .center_x(Length::Fill);
+    widget::mouse_area(text)
+        .on_double_click(Msg::StartRename(workspace.handle().clone()))
+        .into()
+}
+
+/// Smallest width and height a workspace preview is shown at by `workspace_item`,
+/// for either orientation
+pub(crate) const WORKSPACE_PREVIEW_MIN_SIZE: (u32, u32) = (160, 126);
+
+#[allow(clippy::too_many_arguments)]
 fn workspace_item(
     workspace: &Workspace,
     _output: &wl_output::WlOutput,
     layout: WorkspaceLayout,
     is_drop_target: bool,
     has_workspace_drag: bool,
+    name: String,
+    rename: Option<String>,
+    can_remove: bool,
+    alpha: f32,
 ) -> cosmic::Element<'static, Msg> {
     let (mut image, image_height, image_width) = if let Some(img) = workspace.img.as_ref() {
         let is_rotated = matches!(
@@ -254,21 +355,21 @@ fn workspace_item(
         if effective_width > effective_height {
             (
                 // Landscape: fix height
-                widget::container(capture_image(Some(img), 1.0)).max_height(126.0),
+                widget::container(capture_image(Some(img), alpha)).max_height(126.0),
                 126.0,
                 126.0 * effective_width as f32 / effective_height as f32,
             )
         } else {
             (
                 // Portrait: fix width
-                widget::container(capture_image(Some(img), 1.0)).max_width(160),
+                widget::container(capture_image(Some(img), alpha)).max_width(160),
                 160.0 * effective_height as f32 / effective_width as f32,
                 160.0,
             )
         }
     } else {
         (
-            widget::container(capture_image(None, 1.0))
+            widget::container(capture_image(None, alpha))
                 .max_height(126.0)
                 .max_width(224.0),
             126.0,
@@ -277,11 +378,8 @@ fn workspace_item(
     };
 
     let workspace_footer = row![
-        widget::space::horizontal().width(Length::Fixed(32.0)),
-        widget::text::body(fl!("workspace", number = workspace.info.name.as_str()))
-            .ellipsize(Ellipsize::Middle(EllipsizeHeightLimit::Lines(1)))
-            .apply(widget::container)
-            .center_x(Length::Fill),
+        remove_workspace_button(workspace, can_remove),
+        workspace_name(workspace, name, rename),
         pin_button(workspace),
     ];
 
@@ -297,7 +395,6 @@ fn workspace_item(
         .max_width(image_width);
 
     let is_active = workspace.is_active() && !has_workspace_drag;
-    // TODO editable name?
     let mut button = widget::button::custom(content)
         .selected(is_active)
         .class(cosmic::theme::Button::Custom {
@@ -329,6 +426,7 @@ fn workspace_drag_placeholder(
     other_workspace: &Workspace,
     other_output: &wl_output::WlOutput,
     layout: WorkspaceLayout,
+    other_name: String,
 ) -> cosmic::Element<'static, Msg> {
     let drop_target = DropTarget::WorkspaceSidebarDragPlaceholder(
         other_workspace.handle().clone(),
@@ -347,12 +445,23 @@ fn workspace_drag_placeholder(
     })
     .padding(8);
     let placeholder = crate::widgets::match_size(
-        workspace_item(other_workspace, other_output, layout, true, true),
+        workspace_item(
+            other_workspace,
+            other_output,
+            layout,
+            true,
+            true,
+            other_name,
+            None,
+            false,
+            1.0,
+        ),
         placeholder,
     );
     dnd_destination_for_target(drop_target, placeholder.into(), Msg::DndWorkspaceDrop)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn workspace_sidebar_entry<'a>(
     workspace: &'a Workspace,
     output: &'a wl_output::WlOutput,
@@ -360,6 +469,9 @@ fn workspace_sidebar_entry<'a>(
     is_drop_target: bool,
     has_toplevels: bool,
     has_workspace_drag: bool,
+    name: String,
+    rename: Option<String>,
+    alpha: f32,
 ) -> cosmic::Element<'a, Msg> {
     /* XXX
     let mouse_interaction = if is_drop_target {
@@ -368,12 +480,20 @@ fn workspace_sidebar_entry<'a>(
         iced::mouse::Interaction::Idle
     };
     */
+    let drag_icon_name = name.clone();
+    // Cosmic-comp removes empty workspaces that aren't pinned, unless it is the
+    // last one
+    let can_remove = workspace.can_remove() && (!has_toplevels || workspace.is_pinned());
     let item = workspace_item(
         workspace,
         output,
         layout,
         is_drop_target,
         has_workspace_drag,
+        name,
+        rename,
+        can_remove && !has_workspace_drag,
+        alpha,
     );
     let item = iced::widget::mouse_area(item)
         .on_enter(Msg::EnteredWorkspaceSidebarEntry(
@@ -405,24 +525,62 @@ fn workspace_sidebar_entry<'a>(
             DragSurface::Workspace(workspace.handle().clone()),
             Some(workspace.dnd_source_id.clone()),
             destination,
-            move || workspace_item(&workspace_clone, &output_clone, layout, false, true),
+            move || {
+                workspace_item(
+                    &workspace_clone,
+                    &output_clone,
+                    layout,
+                    false,
+                    true,
+                    drag_icon_name.clone(),
+                    None,
+                    false,
+                    1.0,
+                )
+            },
         )
     } else {
         destination
     }
 }
 
-#[allow(clippy::mutable_key_type)]
+/// Direction of the sidebar, relative to the toplevel previews
+fn sidebar_direction(layout: WorkspaceLayout) -> Direction {
+    match layout {
+        WorkspaceLayout::Vertical => Direction::Left,
+        WorkspaceLayout::Horizontal => Direction::Up,
+    }
+}
+
+/// Direction of the toplevel previews, relative to the sidebar
+fn previews_direction(layout: WorkspaceLayout) -> Direction {
+    match layout {
+        WorkspaceLayout::Vertical => Direction::Right,
+        WorkspaceLayout::Horizontal => Direction::Down,
+    }
+}
+
+#[allow(clippy::mutable_key_type, clippy::too_many_arguments)]
 fn workspaces_sidebar<'a>(
     workspaces: impl Iterator<Item = &'a Workspace>,
-    workspaces_with_toplevels: &HashSet<&backend::ExtWorkspaceHandleV1>,
+    workspaces_with_toplevels: &HashSet<&backend::WorkspaceHandle>,
     output: &'a wl_output::WlOutput,
     layout: WorkspaceLayout,
     drop_target: Option<&DropTarget>,
-    drag_workspace: Option<&'a backend::ExtWorkspaceHandleV1>,
+    drag_workspace: Option<&'a backend::WorkspaceHandle>,
+    focus: Option<&Focus>,
+    first_toplevel: Option<backend::ToplevelHandle>,
+    workspace_names: &BTreeMap<Vec<u32>, String>,
+    renaming: Option<&(backend::WorkspaceHandle, String)>,
+    can_create_workspace: bool,
+    alpha: f32,
 ) -> cosmic::Element<'a, Msg> {
     let mut sidebar_entries = Vec::new();
+    // Workspace for each entry in `sidebar_entries`, if it is focusable
+    let mut entry_workspaces = Vec::new();
+    let mut focused = None;
     for workspace in workspaces {
+        let name = workspace.display_name(workspace_names);
         // XXX Need dnd source with same id for drag to work; but give it 0x0 size
         if drag_workspace == Some(workspace.handle()) {
             let workspace_clone = workspace.clone();
@@ -435,9 +593,22 @@ fn workspaces_sidebar<'a>(
                     .width(Length::Shrink)
                     .height(Length::Shrink)
                     .into(),
-                move || workspace_item(&workspace_clone, &output_clone, layout, false, true),
+                move || {
+                    workspace_item(
+                        &workspace_clone,
+                        &output_clone,
+                        layout,
+                        false,
+                        true,
+                        name.clone(),
+                        None,
+                        false,
+                        1.0,
+                    )
+                },
             );
             sidebar_entries.push(source);
+            entry_workspaces.push(None);
             continue;
         }
 
@@ -461,8 +632,18 @@ fn workspaces_sidebar<'a>(
             && drag_workspace != Some(workspace.handle())
             && (drop_target_is_workspace || drop_target_is_placeholder)
         {
-            sidebar_entries.push(workspace_drag_placeholder(workspace, output, layout));
+            sidebar_entries.push(workspace_drag_placeholder(
+                workspace,
+                output,
+                layout,
+                name.clone(),
+            ));
+            entry_workspaces.push(None);
+        }
+        if matches!(focus, Some(Focus::Workspace(handle)) if handle == workspace.handle()) {
+            focused = Some(sidebar_entries.len());
         }
+        entry_workspaces.push(Some(workspace.handle().clone()));
         sidebar_entries.push(workspace_sidebar_entry(
             workspace,
             output,
@@ -470,24 +651,65 @@ fn workspaces_sidebar<'a>(
             drop_target_is_workspace && drag_workspace.is_none(),
             workspaces_with_toplevels.contains(workspace.handle()),
             drag_workspace.is_some(),
+            name,
+            renaming
+                .filter(|(handle, _)| handle == workspace.handle())
+                .map(|(_, name)| name.clone()),
+            alpha,
         ));
     }
+    if can_create_workspace && drag_workspace.is_none() {
+        sidebar_entries.push(new_workspace_button(output, layout));
+        entry_workspaces.push(None);
+    }
     let (axis, width, height) = match layout {
         WorkspaceLayout::Vertical => (Axis::Vertical, Length::Shrink, Length::Fill),
         WorkspaceLayout::Horizontal => (Axis::Horizontal, Length::Fill, Length::Shrink),
     };
-    let sidebar_entries_container =
-        widget::container(crate::widgets::workspace_bar(sidebar_entries, axis)).padding(8.0);
+    let workspace_bar = crate::widgets::workspace_bar(sidebar_entries, axis)
+        .focused(focused)
+        .on_navigate(move |navigation| match navigation {
+            Navigation::Focus(idx) => match entry_workspaces.get(idx) {
+                Some(Some(handle)) => Msg::SetFocus(Some(Focus::Workspace(handle.clone()))),
+                _ => Msg::Ignore,
+            },
+            Navigation::Exit(direction) if direction == previews_direction(layout) => {
+                match &first_toplevel {
+                    Some(handle) => Msg::SetFocus(Some(Focus::Toplevel(handle.clone()))),
+                    None => Msg::Ignore,
+                }
+            }
+            Navigation::Exit(_) => Msg::Ignore,
+        });
+    let sidebar_entries_container = widget::container(workspace_bar).padding(8.0);
+    // Drops on the empty area after the entries
+    let sidebar_entries_container = dnd_destination_for_target(
+        DropTarget::WorkspacesBar(output.clone()),
+        widget::container(sidebar_entries_container)
+            .width(width)
+            .height(height)
+            .into(),
+        |drag: Drag| match drag {
+            Drag::Toplevel => Msg::DndToplevelDrop(DragToplevel {}),
+            Drag::Workspace => Msg::DndWorkspaceDrop(DragWorkspace {}),
+        },
+    );
 
     widget::container(
         widget::container(sidebar_entries_container)
             .width(width)
             .height(height)
-            .class(cosmic::theme::Container::custom(|theme| {
+            .class(cosmic::theme::Container::custom(move |theme| {
+                let on_bg_color =
+                    iced::Color::from(theme.cosmic().on_bg_color()).scale_alpha(alpha);
                 cosmic::iced::widget::container::Style {
-                    text_color: Some(theme.cosmic().on_bg_color().into()),
-                    icon_color: Some(theme.cosmic().on_bg_color().into()),
-                    background: Some(iced::Color::from(theme.cosmic().background.base).into()),
+                    text_color: Some(on_bg_color),
+                    icon_color: Some(on_bg_color),
+                    background: Some(
+                        iced::Color::from(theme.cosmic().background.base)
+                            .scale_alpha(alpha)
+                            .into(),
+                    ),
                     border: Border {
                         radius: theme
                             .cosmic()
@@ -505,7 +727,40 @@ fn workspaces_sidebar<'a>(
     .into()
 }
 
-fn toplevel_preview(toplevel: &Toplevel, is_being_dragged: bool) -> cosmic::Element<'static, Msg> {
+/// Actions on toplevel previews that the compositor supports
+#[derive(Clone, Copy)]
+struct PreviewActions {
+    activate: bool,
+    close: bool,
+    drag: bool,
+}
+
+impl PreviewActions {
+    fn new(app: &App) -> Self {
+        Self {
+            activate: app.has_toplevel_capability(ToplevelCapability::Activate),
+            close: app.has_toplevel_capability(ToplevelCapability::Close),
+            drag: app.has_toplevel_capability(ToplevelCapability::MoveToWorkspace),
+        }
+    }
+}
+
+/// Height of the title above each toplevel preview, which toplevel layouts leave
+/// room for, since it isn't scaled with the capture image
+pub(crate) const TOPLEVEL_TITLE_HEIGHT: f32 = 36.;
+
+fn toplevel_preview(
+    toplevel: &Toplevel,
+    actions: PreviewActions,
+    is_being_dragged: bool,
+) -> cosmic::Element<'static, Msg> {
+    let activate_msg = actions
+        .activate
+        .then(|| Msg::ActivateToplevel(toplevel.handle.clone()));
+    let close_msg = actions
+        .close
+        .then(|| Msg::CloseToplevel(toplevel.handle.clone()));
+
     let cosmic::cosmic_theme::Spacing {
         space_xxs, space_s, ..
     } = cosmic::theme::active().cosmic().spacing;
@@ -525,7 +780,7 @@ fn toplevel_preview(toplevel: &Toplevel, is_being_dragged: bool) -> cosmic::Elem
 
     let title = row![
         widget::button::custom(label)
-            .on_press(Msg::ActivateToplevel(toplevel.handle.clone()))
+            .on_press_maybe(activate_msg.clone())
             .class(cosmic::theme::Button::Icon)
             .padding([space_xxs, space_s])
             .apply(widget::container)
@@ -544,10 +799,11 @@ fn toplevel_preview(toplevel: &Toplevel, is_being_dragged: bool) -> cosmic::Elem
             }))
             .apply(widget::container)
             .width(Length::Fill),
-        close_button(Msg::CloseToplevel(toplevel.handle.clone()))
+        close_button(close_msg.clone())
     ]
     .spacing(8)
     .padding([0, 0, 2, 0])
+    .height(Length::Fixed(TOPLEVEL_TITLE_HEIGHT))
     .align_y(Alignment::Center);
 
     let alpha = if is_being_dragged { 0.5 } else { 1.0 };
@@ -559,52 +815,265 @@ fn toplevel_preview(toplevel: &Toplevel, is_being_dragged: bool) -> cosmic::Elem
                 .contains(&zcosmic_toplevel_handle_v1::State::Activated),
         )
         .class(cosmic::theme::Button::Image)
-        .on_press(Msg::ActivateToplevel(toplevel.handle.clone()));
+        .on_press_maybe(activate_msg);
 
-    widget::mouse_area(crate::widgets::size_cross_nth(
+    let mut mouse_area = widget::mouse_area(crate::widgets::size_cross_nth(
         vec![title.into(), preview.into()],
         Axis::Vertical,
         1, // Allocate width to match capture image
-    ))
-    .on_middle_press(Msg::CloseToplevel(toplevel.handle.clone()))
+    ));
+    if let Some(close_msg) = close_msg {
+        mouse_area = mouse_area.on_middle_press(close_msg);
+    }
+    mouse_area
+        .on_right_press(Msg::ToplevelContextMenu(Some(toplevel.handle.clone())))
+        .on_enter(Msg::EnteredToplevelPreview(toplevel.handle.clone(), true))
+        .on_exit(Msg::EnteredToplevelPreview(toplevel.handle.clone(), false))
+        .into()
+}
+
+fn menu_item(label: String, checked: bool, on_press: Msg) -> cosmic::Element<'static, Msg> {
+    let check: cosmic::Element<_> = if checked {
+        widget::icon::from_name("object-select-symbolic")
+            .size(16)
+            .into()
+    } else {
+        widget::Space::new().width(16).height(16).into()
+    };
+    widget::button::custom(
+        row![check, widget::text::body(label)]
+            .spacing(8)
+            .align_y(Alignment::Center),
+    )
+    .class(cosmic::theme::Button::MenuItem)
+    .width(Length::Fill)
+    .padding([4, 16])
+    .on_press(on_press)
     .into()
 }
 
-fn toplevel_previews_entry(
-    toplevel: &Toplevel,
+/// Context menu for a toplevel, with the actions the compositor supports
+fn toplevel_context_menu<'a>(
+    app: &'a App,
+    toplevel: &'a Toplevel,
+) -> Option<cosmic::Element<'a, Msg>> {
+    use zcosmic_toplevel_handle_v1::State;
+
+    let action = |action| Msg::ToplevelAction(toplevel.handle.clone(), action);
+    let has_state = |state| toplevel.info.state.contains(&state);
+
+    let mut items = Vec::new();
+    if app.has_toplevel_capability(ToplevelCapability::Minimize) {
+        let minimized = has_state(State::Minimized);
+        let label = if minimized {
+            fl!("unminimize")
+        } else {
+            fl!("minimize")
+        };
+        items.push(menu_item(
+            label,
+            false,
+            action(ToplevelAction::Minimize(!minimized)),
+        ));
+    }
+    if app.has_toplevel_capability(ToplevelCapability::Maximize) {
+        let maximized = has_state(State::Maximized);
+        let label = if maximized {
+            fl!("unmaximize")
+        } else {
+            fl!("maximize")
+        };
+        items.push(menu_item(
+            label,
+            false,
+            action(ToplevelAction::Maximize(!maximized)),
+        ));
+    }
+    if app.has_toplevel_capability(ToplevelCapability::Fullscreen) {
+        let fullscreen = has_state(State::Fullscreen);
+        let label = if fullscreen {
+            fl!("unfullscreen")
+        } else {
+            fl!("fullscreen")
+        };
+        items.push(menu_item(
+            label,
+            false,
+            action(ToplevelAction::Fullscreen(!fullscreen)),
+        ));
+    }
+    if app.has_toplevel_capability(ToplevelCapability::Sticky) {
+        let sticky = has_state(State::Sticky);
+        items.push(menu_item(
+            fl!("sticky"),
+            sticky,
+            action(ToplevelAction::Sticky(!sticky)),
+        ));
+    }
+
+    if app.has_toplevel_capability(ToplevelCapability::MoveToWorkspace) {
+        let mut move_items = Vec::new();
+        for output in &toplevel.info.output {
+            for workspace in app.workspaces.for_output(output) {
+                if toplevel.info.workspace.contains(workspace.handle()) {
+                    continue;
+                }
+                let name = workspace.display_name(&app.conf.config.workspace_names);
+                move_items.push(menu_item(
+                    fl!("move-to-workspace", name = name),
+                    false,
+                    action(ToplevelAction::MoveToWorkspace(
+                        workspace.handle().clone(),
+                        output.clone(),
+                    )),
+                ));
+            }
+        }
+        for output in &app.outputs {
+            if toplevel.info.output.contains(&output.handle)
+                || !app
+                    .workspaces
+                    .for_output(&output.handle)
+                    .any(|w| w.is_active())
+            {
+                continue;
+            }
+            move_items.push(menu_item(
+                fl!("move-to-output", name = output.name.as_str()),
+                false,
+                action(ToplevelAction::MoveToOutput(output.handle.clone())),
+            ));
+        }
+        if !items.is_empty() && !move_items.is_empty() {
+            items.push(widget::divider::horizontal::default().into());
+        }
+        items.extend(move_items);
+    }
+
+    if items.is_empty() {
+        return None;
+    }
+    Some(
+        widget::column::with_children(items)
+            .width(Length::Fixed(260.0))
+            .apply(widget::container)
+            .padding(4)
+            .class(cosmic::theme::Container::Dropdown)
+            .into(),
+    )
+}
+
+fn toplevel_previews_entry<'a>(
+    toplevel: &'a Toplevel,
+    actions: PreviewActions,
     is_being_dragged: bool,
-) -> cosmic::Element<'_, Msg> {
+    context_menu: Option<cosmic::Element<'a, Msg>>,
+) -> cosmic::Element<'a, Msg> {
+    let preview = toplevel_preview(toplevel, actions, is_being_dragged);
+    let preview = if let Some(context_menu) = context_menu {
+        widget::popover(preview)
+            .popup(context_menu)
+            .on_close(Msg::ToplevelContextMenu(None))
+            .into()
+    } else {
+        preview
+    };
     // Dragged window still takes up space until moved, but isn't rendered while drag surface is
     // shown.
-    let preview = crate::widgets::visibility_wrapper(
-        toplevel_preview(toplevel, is_being_dragged),
-        !is_being_dragged,
-    );
+    let preview = crate::widgets::visibility_wrapper(preview, !is_being_dragged);
+    // Dropping moves the toplevel to a workspace, so don't allow dragging if that's not supported
+    if !actions.drag {
+        return preview.into();
+    }
     let toplevel2 = toplevel.clone();
     dnd_source_with_drag_surface(
         DragToplevel {},
         DragSurface::Toplevel(toplevel.handle.clone()),
         None,
         preview.into(),
-        move || toplevel_preview(&toplevel2, true),
+        move || toplevel_preview(&toplevel2, actions, true),
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 fn toplevel_previews<'a>(
     toplevels: impl Iterator<Item = &'a Toplevel>,
+    output: &wl_output::WlOutput,
+    actions: PreviewActions,
     layout: WorkspaceLayout,
-    drag_toplevel: Option<&'a backend::ExtForeignToplevelHandleV1>,
+    toplevel_layout: &ToplevelLayoutConfig,
+    transition: f32,
+    animate: bool,
+    drag_toplevel: Option<&'a backend::ToplevelHandle>,
+    focus: Option<&Focus>,
+    active_workspace: Option<backend::WorkspaceHandle>,
+    search: &str,
+    context_menu: Option<(&'a backend::ToplevelHandle, cosmic::Element<'a, Msg>)>,
 ) -> cosmic::Element<'a, Msg> {
     let (width, height) = match layout {
         WorkspaceLayout::Vertical => (Length::FillPortion(4), Length::Fill),
         WorkspaceLayout::Horizontal => (Length::Fill, Length::FillPortion(4)),
     };
+    let toplevels = toplevels.collect::<Vec<_>>();
+    let focused = toplevels
+        .iter()
+        .position(|t| matches!(focus, Some(Focus::Toplevel(handle)) if *handle == t.handle));
+    let handles = toplevels
+        .iter()
+        .map(|t| t.handle.clone())
+        .collect::<Vec<_>>();
+    let keys = handles.iter().map(|h| h.protocol_id().into()).collect();
+    let resize_handles = handles.clone();
+    let resize_output = output.clone();
+    let geometry = toplevels
+        .iter()
+        .map(|t| {
+            let geometry = t.info.geometry.get(output)?;
+            Some(iced::Rectangle::new(
+                iced::Point::new(geometry.x as f32, geometry.y as f32),
+                iced::Size::new(geometry.width as f32, geometry.height as f32),
+            ))
+        })
+        .collect();
+    let mut context_menu = context_menu;
     let entries = toplevels
-        .map(|t| toplevel_previews_entry(t, drag_toplevel == Some(&t.handle)))
+        .into_iter()
+        .map(|t| {
+            let menu = context_menu
+                .take_if(|(handle, _)| **handle == t.handle)
+                .map(|(_, menu)| menu);
+            toplevel_previews_entry(t, actions, drag_toplevel == Some(&t.handle), menu)
+        })
         .collect();
+    let toplevels = crate::widgets::toplevels(entries)
+        .layout(toplevel_layout)
+        .geometry(geometry)
+        .keys(keys)
+        .transition(transition)
+        .animate(animate)
+        .focused(focused)
+        .on_resize(move |sizes| {
+            let sizes = resize_handles.iter().cloned().zip(sizes).collect();
+            Msg::ToplevelPreviewSizes(resize_output.clone(), sizes)
+        })
+        .on_navigate(move |navigation| match navigation {
+            Navigation::Focus(idx) => Msg::SetFocus(handles.get(idx).cloned().map(Focus::Toplevel)),
+            Navigation::Exit(direction) if direction == sidebar_direction(layout) => {
+                match &active_workspace {
+                    Some(handle) => Msg::SetFocus(Some(Focus::Workspace(handle.clone()))),
+                    None => Msg::Ignore,
+                }
+            }
+            Navigation::Exit(_) => Msg::Ignore,
+        });
+    let content = if search.is_empty() {
+        cosmic::Element::from(toplevels)
+    } else {
+        column![search_label(search), toplevels].spacing(12).into()
+    };
     //row(entries)
     widget::mouse_area(
-        widget::container(crate::widgets::toplevels(entries))
+        widget::container(content)
             .align_x(Alignment::Center)
             .width(width)
             .height(height)
@@ -614,6 +1083,18 @@ fn toplevel_previews<'a>(
     .into()
 }
 
+fn search_label(search: &str) -> cosmic::Element<'static, Msg> {
+    row![
+        widget::icon::from_name("system-search-symbolic").size(16),
+        widget::text::title4(search.to_owned()),
+    ]
+    .spacing(8)
+    .align_y(Alignment::Center)
+    .apply(widget::container)
+    .center_x(Length::Fill)
+    .into()
+}
+
 fn capture_image(image: Option<&CaptureImage>, alpha: f32) -> cosmic::Element<'static, Msg> {
     if let Some(image) = image {
         #[cfg(feature = "no-subsurfaces")]
@@ -0,0 +1,146 @@
+//! Shared helpers for arrow key navigation within the [`Toplevels`](super::toplevels) and
+//! [`WorkspaceBar`](super::workspace_bar) widgets.
+
+use cosmic::iced::advanced::renderer::{self, Renderer as _};
+use cosmic::iced::keyboard::Key;
+use cosmic::iced::keyboard::key::Named;
+use cosmic::iced::{Border, Color, Rectangle};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    pub fn from_key(key: &Key) -> Option<Self> {
+        match key {
+            Key::Named(Named::ArrowLeft) => Some(Self::Left),
+            Key::Named(Named::ArrowRight) => Some(Self::Right),
+            Key::Named(Named::ArrowUp) => Some(Self::Up),
+            Key::Named(Named::ArrowDown) => Some(Self::Down),
+            _ => None,
+        }
+    }
+}
+
+/// Result of an arrow key press in a widget that has a focused child
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Navigation {
+    /// Focus the child with this index
+    Focus(usize),
+    /// No child in that direction; focus should leave the widget
+    Exit(Direction),
+}
+
+/// Find the closest rectangle in `direction` from `rects[from]`, based on what is
+/// actually on screen.
+///
+/// Offset perpendicular to `direction` is weighted more heavily, so moving right
+/// prefers the next preview in the same row over a closer one in another row.
+pub fn spatial_neighbor(rects: &[Rectangle], from: usize, direction: Direction) -> Option<usize> {
+    let from_center = rects.get(from)?.center();
+    rects
+        .iter()
+        .enumerate()
+        // Skip zero-size placeholders, like the hidden `dnd_source` of a dragged workspace
+        .filter(|(i, rect)| *i != from && rect.width > 0. && rect.height > 0.)
+        .filter_map(|(i, rect)| {
+            let center = rect.center();
+            let (main, cross) = match direction {
+                Direction::Left => (from_center.x - center.x, center.y - from_center.y),
+                Direction::Right => (center.x - from_center.x, center.y - from_center.y),
+                Direction::Up => (from_center.y - center.y, center.x - from_center.x),
+                Direction::Down => (center.y - from_center.y, center.x - from_center.x),
+            };
+            (main > 0.).then_some((i, main + 2. * cross.abs()))
+        })
+        .min_by(|(_, cost1), (_, cost2)| cost1.total_cmp(cost2))
+        .map(|(i, _)| i)
+}
+
+/// Draw a focus ring just outside `bounds`.
+///
+/// Drawn outside the bounds, since capture images are subsurfaces that will be
+/// shown over anything drawn on the main surface.
+pub fn draw_focus_ring(renderer: &mut cosmic::Renderer, theme: &cosmic::Theme, bounds: Rectangle) {
+    let cosmic = theme.cosmic();
+    renderer.fill_quad(
+        renderer::Quad {
+            bounds: bounds.expand(4.),
+            border: Border {
+                color: cosmic.accent.base.into(),
+                width: 2.,
+                radius: cosmic.radius_s().into(),
+            },
+            ..Default::default()
+        },
+        Color::TRANSPARENT,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmic::iced::{Point, Size};
+
+    fn rect(x: f32, y: f32) -> Rectangle {
+        Rectangle::new(Point::new(x, y), Size::new(80., 80.))
+    }
+
+    // 0 1 2
+    // 3 4 5
+    fn grid() -> Vec<Rectangle> {
+        vec![
+            rect(0., 0.),
+            rect(100., 0.),
+            rect(200., 0.),
+            rect(0., 100.),
+            rect(100., 100.),
+            rect(200., 100.),
+        ]
+    }
+
+    #[test]
+    fn neighbor_in_each_direction() {
+        let rects = grid();
+        assert_eq!(spatial_neighbor(&rects, 4, Direction::Left), Some(3));
+        assert_eq!(spatial_neighbor(&rects, 4, Direction::Right), Some(5));
+        assert_eq!(spatial_neighbor(&rects, 4, Direction::Up), Some(1));
+        assert_eq!(spatial_neighbor(&rects, 1, Direction::Down), Some(4));
+    }
+
+    #[test]
+    fn neighbor_prefers_same_row() {
+        let mut rects = grid();
+        // Closer on the main axis, but in the other row
+        rects[4] = rect(60., 100.);
+        assert_eq!(spatial_neighbor(&rects, 0, Direction::Right), Some(1));
+        assert_eq!(spatial_neighbor(&rects, 2, Direction::Left), Some(1));
+        assert_eq!(spatial_neighbor(&rects, 5, Direction::Left), Some(4));
+    }
+
+    #[test]
+    fn neighbor_tie_picks_first() {
+        let rects = vec![rect(100., 100.), rect(0., 0.), rect(0., 200.)];
+        assert_eq!(spatial_neighbor(&rects, 0, Direction::Left), Some(1));
+        let rects = vec![rect(100., 100.), rect(0., 200.), rect(0., 0.)];
+        assert_eq!(spatial_neighbor(&rects, 0, Direction::Left), Some(1));
+    }
+
+    #[test]
+    fn no_neighbor() {
+        let rects = grid();
+        assert_eq!(spatial_neighbor(&rects, 3, Direction::Left), None);
+        assert_eq!(spatial_neighbor(&rects, 2, Direction::Right), None);
+        assert_eq!(spatial_neighbor(&rects, 1, Direction::Up), None);
+        assert_eq!(spatial_neighbor(&rects, 4, Direction::Down), None);
+        assert_eq!(spatial_neighbor(&rects, 6, Direction::Down), None);
+
+        // Zero-size placeholders are skipped
+        let rects = vec![rect(100., 0.), Rectangle::default()];
+        assert_eq!(spatial_neighbor(&rects, 0, Direction::Left), None);
+    }
+}
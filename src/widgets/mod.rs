@@ -5,6 +5,8 @@ use cosmic::iced::{Length, Rectangle, Size, Vector};
 use std::marker::PhantomData;
 
 mod image_bg;
+mod keyboard_nav;
+pub use keyboard_nav::{Direction, Navigation};
 mod workspace_bar;
 pub use workspace_bar::workspace_bar;
 mod size_cross_nth;
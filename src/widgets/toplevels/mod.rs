@@ -1,34 +1,185 @@
-use cosmic::iced::advanced::layout::flex::Axis;
 use cosmic::iced::advanced::layout::{self};
-use cosmic::iced::advanced::widget::{Operation, Tree};
-use cosmic::iced::advanced::{Clipboard, Layout, Shell, Widget, mouse, renderer};
+use cosmic::iced::advanced::renderer::{self, Renderer as _};
+use cosmic::iced::advanced::widget::{Operation, Tree, tree};
+use cosmic::iced::advanced::{Clipboard, Layout, Shell, Widget, mouse};
 use cosmic::iced::event::Event;
-use cosmic::iced::{Length, Rectangle, Size, Vector};
+use cosmic::iced::{Length, Point, Rectangle, Size, Vector, keyboard, window};
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::time::Instant;
+
+use crate::animation;
+
+use super::keyboard_nav::{self, Direction, Navigation};
 
 mod toplevel_layout;
-use toplevel_layout::{LayoutToplevel, ToplevelLayout, TwoRowColToplevelLayout};
+use toplevel_layout::{AnyToplevelLayout, LayoutToplevel, ToplevelLayout};
 
-pub fn toplevels<Msg>(children: Vec<cosmic::Element<Msg>>) -> Toplevels<Msg> {
+pub fn toplevels<'a, Msg>(children: Vec<cosmic::Element<'a, Msg>>) -> Toplevels<'a, Msg> {
     Toplevels {
-        // TODO configurable
-        layout: TwoRowColToplevelLayout::new(Axis::Horizontal, 16),
+        layout: AnyToplevelLayout::new(&crate::ToplevelLayoutConfig::default()),
         children,
+        geometry: Vec::new(),
+        keys: Vec::new(),
+        transition: 1.,
+        animate: false,
+        focused: None,
+        on_navigate: None,
+        on_resize: None,
         _msg: PhantomData,
     }
 }
 
 pub struct Toplevels<'a, Msg> {
-    layout: TwoRowColToplevelLayout,
+    layout: AnyToplevelLayout,
     children: Vec<cosmic::Element<'a, Msg>>,
+    geometry: Vec<Option<Rectangle>>,
+    keys: Vec<u64>,
+    transition: f32,
+    animate: bool,
+    focused: Option<usize>,
+    on_navigate: Option<Box<dyn Fn(Navigation) -> Msg + 'a>>,
+    on_resize: Option<Box<dyn Fn(Vec<Size>) -> Msg + 'a>>,
     _msg: PhantomData<Msg>,
 }
 
+impl<'a, Msg> Toplevels<'a, Msg> {
+    /// Algorithm, axis, and spacing used to arrange children
+    pub fn layout(mut self, config: &crate::ToplevelLayoutConfig) -> Self {
+        self.layout = AnyToplevelLayout::new(config);
+        self
+    }
+
+    /// Position and size of the toplevel shown by each child, on the output, if known
+    pub fn geometry(mut self, geometry: Vec<Option<Rectangle>>) -> Self {
+        self.geometry = geometry;
+        self
+    }
+
+    /// Identifier for the toplevel shown by each child, used to animate a child
+    /// moving to a new rectangle
+    pub fn keys(mut self, keys: Vec<u64>) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Progress of the overview open/close transition, from 0 (hidden) to 1 (shown)
+    ///
+    /// Children are zoomed from their `geometry` to their assigned rectangle.
+    pub fn transition(mut self, progress: f32) -> Self {
+        self.transition = progress;
+        self
+    }
+
+    /// Animate children moving to a new rectangle when the layout changes
+    pub fn animate(mut self, animate: bool) -> Self {
+        self.animate = animate;
+        self
+    }
+
+    /// Index of child that has keyboard focus, and should have a focus ring drawn
+    pub fn focused(mut self, focused: Option<usize>) -> Self {
+        self.focused = focused;
+        self
+    }
+
+    /// Called when an arrow key is pressed while a child is focused
+    pub fn on_navigate(mut self, on_navigate: impl Fn(Navigation) -> Msg + 'a) -> Self {
+        self.on_navigate = Some(Box::new(on_navigate));
+        self
+    }
+
+    /// Called with the size of the rectangle assigned to each child, when changed
+    pub fn on_resize(mut self, on_resize: impl Fn(Vec<Size>) -> Msg + 'a) -> Self {
+        self.on_resize = Some(Box::new(on_resize));
+        self
+    }
+}
+
+impl<Msg> Toplevels<'_, Msg> {
+    /// Offset of child `i` while zooming from its `geometry`, which is relative to the
+    /// output rather than the widget at `origin`
+    fn zoom_offset(&self, i: usize, origin: Point) -> Vector {
+        if self.transition < 1. && self.geometry.get(i).copied().flatten().is_some() {
+            Vector::new(-origin.x, -origin.y) * (1. - self.transition)
+        } else {
+            Vector::ZERO
+        }
+    }
+}
+
+/// A child moving from one rectangle to another
+struct RectAnimation {
+    from: Rectangle,
+    to: Rectangle,
+    start: Instant,
+}
+
+impl RectAnimation {
+    fn rect(&self, now: Instant) -> Rectangle {
+        let t = animation::ease(animation::linear_progress(self.start, now));
+        animation::lerp_rect(self.from, self.to, t)
+    }
+
+    fn is_finished(&self, now: Instant) -> bool {
+        animation::linear_progress(self.start, now) >= 1.
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Rectangle assigned to each child by key
+    rects: HashMap<u64, RectAnimation>,
+    /// A child wasn't yet at its final rectangle, as of the last layout
+    animating: bool,
+    /// Size of rectangle assigned to each child, as of the last layout
+    sizes: Vec<Size>,
+    /// Sizes last passed to `on_resize`
+    published_sizes: Vec<Size>,
+}
+
+impl State {
+    /// Rectangle to show a child at, animating if `target` has changed
+    fn rect(
+        &mut self,
+        key: Option<u64>,
+        target: Rectangle,
+        animate: bool,
+        now: Instant,
+    ) -> Rectangle {
+        let Some(key) = key else {
+            return target;
+        };
+        let entry = self.rects.entry(key).or_insert(RectAnimation {
+            from: target,
+            to: target,
+            start: now,
+        });
+        if entry.to != target {
+            let from = if animate { entry.rect(now) } else { target };
+            *entry = RectAnimation {
+                from,
+                to: target,
+                start: now,
+            };
+        }
+        entry.rect(now)
+    }
+}
+
 impl<Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for Toplevels<'_, Msg> {
     fn size(&self) -> Size<Length> {
         self.layout.size()
     }
 
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
     fn layout(
         &mut self,
         tree: &mut Tree,
@@ -40,10 +191,12 @@ impl<Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for Toplevels<'_, Msg> {
             .children
             .iter_mut()
             .zip(tree.children.iter_mut())
-            .map(|(child, tree)| {
+            .enumerate()
+            .map(|(i, (child, tree))| {
                 let preferred_size = child.as_widget_mut().layout(tree, renderer, limits).size();
                 LayoutToplevel {
                     preferred_size,
+                    geometry: self.geometry.get(i).copied().flatten(),
                     _phantom_data: PhantomData,
                 }
             })
@@ -52,12 +205,28 @@ impl<Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for Toplevels<'_, Msg> {
         // Assign rectangles for each child using `ToplevelLayout` backend
         let assigned_rects = self.layout.layout(limits.max(), &layout_toplevels);
 
+        let now = Instant::now();
+        let state = tree.state.downcast_mut::<State>();
+        state.rects.retain(|key, _| self.keys.contains(key));
+        state.sizes.clear();
         let nodes = self
             .children
             .iter_mut()
             .zip(tree.children.iter_mut())
             .zip(assigned_rects)
-            .map(|((child, tree), assigned_rect)| {
+            .enumerate()
+            .map(|(i, ((child, tree), assigned_rect))| {
+                state.sizes.push(assigned_rect.size());
+                let key = self.keys.get(i).copied();
+                let mut assigned_rect = state.rect(key, assigned_rect, self.animate, now);
+                // Zoom from where the toplevel is on the output. The position of the
+                // widget isn't known here, so `draw` offsets the child by `zoom_offset`.
+                if self.transition < 1.
+                    && let Some(geometry) = layout_toplevels[i].geometry
+                {
+                    assigned_rect = animation::lerp_rect(geometry, assigned_rect, self.transition);
+                }
+
                 let child_limits = layout::Limits::new(Size::ZERO, assigned_rect.size());
                 let layout = child.as_widget_mut().layout(tree, renderer, &child_limits);
 
@@ -70,6 +239,7 @@ impl<Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for Toplevels<'_, Msg> {
                 layout.move_to(assigned_rect.position() + centering_offset)
             })
             .collect();
+        state.animating = state.rects.values().any(|rect| !rect.is_finished(now));
         layout::Node::with_children(limits.max(), nodes)
     }
 
@@ -105,6 +275,37 @@ impl<Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for Toplevels<'_, Msg> {
         shell: &mut Shell<'_, Msg>,
         viewport: &Rectangle,
     ) {
+        if let Event::Window(window::Event::RedrawRequested(_)) = event {
+            let state = tree.state.downcast_mut::<State>();
+            if state.animating {
+                shell.invalidate_layout();
+                shell.request_redraw();
+            }
+            if let Some(on_resize) = &self.on_resize
+                && state.sizes != state.published_sizes
+            {
+                state.published_sizes = state.sizes.clone();
+                shell.publish(on_resize(state.sizes.clone()));
+            }
+        }
+
+        if let Some(focused) = self.focused
+            && let Some(on_navigate) = &self.on_navigate
+            && let Event::Keyboard(keyboard::Event::KeyPressed { key, .. }) = event
+            && let Some(direction) = Direction::from_key(key)
+        {
+            // Navigate based on rectangles assigned by `ToplevelLayout`, so directions
+            // match what is on screen.
+            let rects = layout.children().map(|l| l.bounds()).collect::<Vec<_>>();
+            let navigation = match keyboard_nav::spatial_neighbor(&rects, focused, direction) {
+                Some(idx) => Navigation::Focus(idx),
+                None => Navigation::Exit(direction),
+            };
+            shell.publish(on_navigate(navigation));
+            shell.capture_event();
+            return;
+        }
+
         for ((child, state), layout) in self
             .children
             .iter_mut()
@@ -149,15 +350,22 @@ impl<Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for Toplevels<'_, Msg> {
         viewport: &Rectangle,
     ) {
         if let Some(viewport) = layout.bounds().intersection(viewport) {
-            for ((child, state), layout) in self
+            let origin = layout.position();
+            for (i, ((child, state), layout)) in self
                 .children
                 .iter()
                 .zip(&tree.children)
                 .zip(layout.children())
+                .enumerate()
             {
-                child
-                    .as_widget()
-                    .draw(state, renderer, theme, style, layout, cursor, &viewport);
+                renderer.with_translation(self.zoom_offset(i, origin), |renderer| {
+                    child
+                        .as_widget()
+                        .draw(state, renderer, theme, style, layout, cursor, &viewport);
+                });
+            }
+            if let Some(layout) = self.focused.and_then(|i| layout.children().nth(i)) {
+                keyboard_nav::draw_focus_ring(renderer, theme, layout.bounds());
             }
         }
     }
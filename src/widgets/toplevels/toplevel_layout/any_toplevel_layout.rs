@@ -0,0 +1,75 @@
+use cosmic::iced::advanced::layout::flex::Axis;
+use cosmic::iced::{Length, Rectangle, Size};
+use itertools::Either;
+
+use super::{
+    LayoutToplevel, MultiRowColToplevelLayout, NaturalToplevelLayout, RowColToplevelLayout,
+    ToplevelLayout, TwoRowColToplevelLayout,
+};
+use crate::{ToplevelLayoutAxis, ToplevelLayoutConfig, ToplevelLayoutKind};
+
+/// Layout chosen at runtime from [`ToplevelLayoutConfig`]
+pub(crate) enum AnyToplevelLayout {
+    RowCol(RowColToplevelLayout),
+    TwoRowCol(TwoRowColToplevelLayout),
+    MultiRowCol(MultiRowColToplevelLayout),
+    Natural(NaturalToplevelLayout),
+}
+
+impl AnyToplevelLayout {
+    pub fn new(config: &ToplevelLayoutConfig) -> Self {
+        let axis = match config.axis {
+            ToplevelLayoutAxis::Horizontal => Axis::Horizontal,
+            ToplevelLayoutAxis::Vertical => Axis::Vertical,
+        };
+        match config.kind {
+            ToplevelLayoutKind::SingleRow => {
+                Self::RowCol(RowColToplevelLayout::new(axis, config.spacing))
+            }
+            ToplevelLayoutKind::TwoRows => {
+                Self::TwoRowCol(TwoRowColToplevelLayout::new(axis, config.spacing))
+            }
+            ToplevelLayoutKind::Rows(rows) => Self::MultiRowCol(MultiRowColToplevelLayout::new(
+                axis,
+                config.spacing,
+                Some(rows),
+            )),
+            ToplevelLayoutKind::Grid => {
+                Self::MultiRowCol(MultiRowColToplevelLayout::new(axis, config.spacing, None))
+            }
+            ToplevelLayoutKind::Natural => {
+                Self::Natural(NaturalToplevelLayout::new(config.spacing))
+            }
+        }
+    }
+}
+
+impl ToplevelLayout for AnyToplevelLayout {
+    fn size(&self) -> Size<Length> {
+        match self {
+            Self::RowCol(layout) => layout.size(),
+            Self::TwoRowCol(layout) => layout.size(),
+            Self::MultiRowCol(layout) => layout.size(),
+            Self::Natural(layout) => layout.size(),
+        }
+    }
+
+    fn layout(
+        &self,
+        max_limit: Size,
+        toplevels: &[LayoutToplevel<'_>],
+    ) -> impl Iterator<Item = Rectangle> {
+        match self {
+            Self::RowCol(layout) => Either::Left(Either::Left(layout.layout(max_limit, toplevels))),
+            Self::TwoRowCol(layout) => {
+                Either::Left(Either::Right(layout.layout(max_limit, toplevels)))
+            }
+            Self::MultiRowCol(layout) => {
+                Either::Right(Either::Left(layout.layout(max_limit, toplevels)))
+            }
+            Self::Natural(layout) => {
+                Either::Right(Either::Right(layout.layout(max_limit, toplevels)))
+            }
+        }
+    }
+}
@@ -91,6 +91,7 @@ impl<T: AxisToplevelLayout> ToplevelLayout for T {
             .iter()
             .map(|t| LayoutToplevel {
                 preferred_size: AxisSize::unpack(self.axis(), t.preferred_size),
+                geometry: t.geometry,
                 _phantom_data: PhantomData,
             })
             .collect::<Vec<_>>();
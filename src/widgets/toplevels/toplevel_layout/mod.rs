@@ -4,9 +4,14 @@
 use cosmic::iced::{Length, Rectangle, Size};
 use std::marker::PhantomData;
 
+mod any_toplevel_layout;
+pub(crate) use any_toplevel_layout::AnyToplevelLayout;
 mod axis_toplevel_layout;
+mod multi_row_col_toplevel_layout;
+pub(crate) use multi_row_col_toplevel_layout::MultiRowColToplevelLayout;
+mod natural_toplevel_layout;
+pub(crate) use natural_toplevel_layout::NaturalToplevelLayout;
 mod row_col_toplevel_layout;
-#[allow(unused_imports)]
 pub(crate) use row_col_toplevel_layout::RowColToplevelLayout;
 mod two_row_col_toplevel_layout;
 pub(crate) use two_row_col_toplevel_layout::TwoRowColToplevelLayout;
@@ -16,6 +21,8 @@ pub(crate) struct LayoutToplevel<'a, S = Size> {
     //toplevel: &'a crate::Toplevel,
     /// Preferred size of the child widget, if it fill the parent container
     pub preferred_size: S,
+    /// Position and size of the toplevel on the output, if known
+    pub geometry: Option<Rectangle>,
     pub _phantom_data: PhantomData<&'a crate::Toplevel>,
 }
 
@@ -0,0 +1,301 @@
+use cosmic::iced::Length;
+use cosmic::iced::advanced::layout::flex::Axis;
+
+use super::LayoutToplevel;
+use super::axis_toplevel_layout::{AxisPoint, AxisRectangle, AxisSize, AxisToplevelLayout};
+use crate::view::TOPLEVEL_TITLE_HEIGHT;
+
+/// Rows considered when there is no configured maximum. Finding the best partition
+/// takes time quadratic in the number of toplevels, for each row count.
+const MAX_ROWS: usize = 12;
+
+/// Generalization of [`super::TwoRowColToplevelLayout`] to any number of rows.
+///
+/// Every row count, and every way of partitioning toplevels (in order) into that many
+/// rows, is considered. The one where the smallest preview is scaled the least is
+/// chosen. Each row is then scaled as much as it can be.
+pub(crate) struct MultiRowColToplevelLayout {
+    axis: Axis,
+    spacing: u32,
+    /// Maximum number of rows, or `None` for up to [`MAX_ROWS`]
+    max_rows: Option<usize>,
+}
+
+/// Per-toplevel sizes that scale, and prefix sums used to find row scales quickly
+struct RowCosts {
+    /// Sum of scalable main axis lengths of toplevels before each index
+    main_prefix: Vec<f32>,
+    /// `cross_max[start][end - start - 1]` is the largest scalable cross axis length
+    /// in `start..end`
+    cross_max: Vec<Vec<f32>>,
+}
+
+impl RowCosts {
+    fn new(toplevels: &[LayoutToplevel<'_, AxisSize>], title: AxisSize) -> Self {
+        let scalable = toplevels
+            .iter()
+            .map(|t| AxisSize {
+                main: (t.preferred_size.main - title.main).max(1.),
+                cross: (t.preferred_size.cross - title.cross).max(1.),
+            })
+            .collect::<Vec<_>>();
+        let main_prefix = std::iter::once(0.)
+            .chain(scalable.iter().scan(0., |total, size| {
+                *total += size.main;
+                Some(*total)
+            }))
+            .collect();
+        let cross_max = (0..scalable.len())
+            .map(|start| {
+                scalable[start..]
+                    .iter()
+                    .scan(0f32, |max, size| {
+                        *max = max.max(size.cross);
+                        Some(*max)
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            main_prefix,
+            cross_max,
+        }
+    }
+}
+
+impl MultiRowColToplevelLayout {
+    pub fn new(axis: Axis, spacing: u32, max_rows: Option<u32>) -> Self {
+        Self {
+            axis,
+            spacing,
+            max_rows: max_rows.map(|rows| (rows as usize).max(1)),
+        }
+    }
+
+    /// Part of each preview that doesn't scale
+    fn title(&self) -> AxisSize {
+        match self.axis {
+            Axis::Horizontal => AxisSize {
+                main: 0.,
+                cross: TOPLEVEL_TITLE_HEIGHT,
+            },
+            Axis::Vertical => AxisSize {
+                main: TOPLEVEL_TITLE_HEIGHT,
+                cross: 0.,
+            },
+        }
+    }
+
+    fn row_limit(&self, max_limit: AxisSize, rows: usize) -> AxisSize {
+        let spacing = self.spacing as f32 * (rows - 1) as f32;
+        AxisSize {
+            main: max_limit.main,
+            cross: (max_limit.cross - spacing) / rows as f32,
+        }
+    }
+
+    /// Scale factor for toplevels `start..end` in a row of size `row_limit`
+    fn row_scale(&self, costs: &RowCosts, row_limit: AxisSize, start: usize, end: usize) -> f32 {
+        let title = self.title();
+        let count = (end - start) as f32;
+        let main = costs.main_prefix[end] - costs.main_prefix[start];
+        let cross = costs.cross_max[start][end - start - 1];
+        let available_main =
+            row_limit.main - self.spacing as f32 * (count - 1.) - title.main * count;
+        let available_cross = row_limit.cross - title.cross;
+        (available_main / main)
+            .min(available_cross / cross)
+            .clamp(0., 1.)
+    }
+
+    /// Best partition into `rows` rows, as the end index of each row, and the
+    /// smallest row scale factor with that partition
+    fn partition(&self, costs: &RowCosts, row_limit: AxisSize, rows: usize) -> (Vec<usize>, f32) {
+        let len = costs.main_prefix.len() - 1;
+        // `best[k][i]` is the best smallest scale with the first `i` toplevels in
+        // `k + 1` rows, and `split[k][i]` is where the last of those rows starts
+        let mut best = vec![vec![f32::NEG_INFINITY; len + 1]; rows];
+        let mut split = vec![vec![0; len + 1]; rows];
+        for end in 1..=len {
+            best[0][end] = self.row_scale(costs, row_limit, 0, end);
+        }
+        for k in 1..rows {
+            for end in k + 1..=len {
+                for start in k..end {
+                    let scale =
+                        best[k - 1][start].min(self.row_scale(costs, row_limit, start, end));
+                    if scale > best[k][end] {
+                        best[k][end] = scale;
+                        split[k][end] = start;
+                    }
+                }
+            }
+        }
+
+        let mut ends = vec![len];
+        let mut end = len;
+        for k in (1..rows).rev() {
+            end = split[k][end];
+            ends.push(end);
+        }
+        ends.reverse();
+        (ends, best[rows - 1][len])
+    }
+}
+
+impl AxisToplevelLayout for MultiRowColToplevelLayout {
+    fn axis(&self) -> &Axis {
+        &self.axis
+    }
+
+    fn size(&self) -> AxisSize<Length> {
+        AxisSize {
+            main: Length::Fill,
+            cross: Length::Shrink,
+        }
+    }
+
+    fn layout(
+        &self,
+        max_limit: AxisSize,
+        toplevels: &[LayoutToplevel<'_, AxisSize>],
+    ) -> impl Iterator<Item = AxisRectangle> {
+        let mut rects = Vec::with_capacity(toplevels.len());
+        if toplevels.is_empty() {
+            return rects.into_iter();
+        }
+
+        let costs = RowCosts::new(toplevels, self.title());
+        let max_rows = self.max_rows.unwrap_or(MAX_ROWS).min(toplevels.len());
+        // Fewer rows are preferred if they don't make previews smaller
+        let (rows, ends, _) = (1..=max_rows)
+            .map(|rows| {
+                let (ends, scale) = self.partition(&costs, self.row_limit(max_limit, rows), rows);
+                (rows, ends, scale)
+            })
+            .reduce(|a, b| if b.2 > a.2 { b } else { a })
+            .unwrap();
+
+        let title = self.title();
+        let spacing = self.spacing as f32;
+        let row_limit = self.row_limit(max_limit, rows);
+        let mut start = 0;
+        for (row, end) in ends.into_iter().enumerate() {
+            let scale = self.row_scale(&costs, row_limit, start, end);
+            let sizes = toplevels[start..end]
+                .iter()
+                .map(|t| (t.preferred_size.main - title.main).max(1.) * scale + title.main)
+                .collect::<Vec<_>>();
+            let total_main = sizes.iter().sum::<f32>() + spacing * (sizes.len() - 1) as f32;
+            // Center row on main axis
+            let mut main = ((row_limit.main - total_main) / 2.).max(0.);
+            let cross = row as f32 * (row_limit.cross + spacing);
+            for size in sizes {
+                rects.push(AxisRectangle::new(
+                    AxisPoint { main, cross },
+                    AxisSize {
+                        main: size,
+                        cross: row_limit.cross,
+                    },
+                ));
+                main += size + spacing;
+            }
+            start = end;
+        }
+        rects.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use super::*;
+
+    /// Toplevels with capture images of these sizes, below a title
+    fn toplevels(sizes: &[(f32, f32)]) -> Vec<LayoutToplevel<'static, AxisSize>> {
+        sizes
+            .iter()
+            .map(|&(main, cross)| LayoutToplevel {
+                preferred_size: AxisSize {
+                    main,
+                    cross: cross + TOPLEVEL_TITLE_HEIGHT,
+                },
+                geometry: None,
+                _phantom_data: PhantomData,
+            })
+            .collect()
+    }
+
+    fn partition(
+        layout: &MultiRowColToplevelLayout,
+        max_limit: AxisSize,
+        toplevels: &[LayoutToplevel<'_, AxisSize>],
+        rows: usize,
+    ) -> (Vec<usize>, f32) {
+        let costs = RowCosts::new(toplevels, layout.title());
+        layout.partition(&costs, layout.row_limit(max_limit, rows), rows)
+    }
+
+    #[test]
+    fn equal_toplevels_split_evenly() {
+        let layout = MultiRowColToplevelLayout::new(Axis::Horizontal, 0, None);
+        let max_limit = AxisSize {
+            main: 1000.,
+            cross: 800.,
+        };
+        let toplevels = toplevels(&[(400., 300.); 4]);
+        assert_eq!(
+            partition(&layout, max_limit, &toplevels, 1),
+            (vec![4], 0.625)
+        );
+        assert_eq!(
+            partition(&layout, max_limit, &toplevels, 2),
+            (vec![2, 4], 1.)
+        );
+    }
+
+    #[test]
+    fn uneven_toplevels_balanced() {
+        let layout = MultiRowColToplevelLayout::new(Axis::Horizontal, 0, None);
+        let max_limit = AxisSize {
+            main: 900.,
+            cross: 800.,
+        };
+        // Splitting in half would need the first row to be scaled down
+        let toplevels = toplevels(&[(800., 300.), (200., 300.), (200., 300.), (200., 300.)]);
+        assert_eq!(
+            partition(&layout, max_limit, &toplevels, 2),
+            (vec![1, 4], 1.)
+        );
+    }
+
+    #[test]
+    fn fewest_rows_with_largest_previews() {
+        let layout = MultiRowColToplevelLayout::new(Axis::Horizontal, 0, None);
+        let max_limit = AxisSize {
+            main: 1000.,
+            cross: 800.,
+        };
+        let rects = layout
+            .layout(max_limit, &toplevels(&[(400., 300.); 4]))
+            .collect::<Vec<_>>();
+        let crosses = rects.iter().map(|r| r.origin.cross).collect::<Vec<_>>();
+        assert_eq!(crosses, vec![0., 0., 400., 400.]);
+        // Previews aren't scaled up past their preferred size
+        assert!(rects.iter().all(|r| r.size.main == 400.));
+    }
+
+    #[test]
+    fn max_rows_respected() {
+        let layout = MultiRowColToplevelLayout::new(Axis::Horizontal, 0, Some(1));
+        let max_limit = AxisSize {
+            main: 1000.,
+            cross: 800.,
+        };
+        let rects = layout
+            .layout(max_limit, &toplevels(&[(400., 300.); 4]))
+            .collect::<Vec<_>>();
+        assert!(rects.iter().all(|r| r.origin.cross == 0.));
+    }
+}
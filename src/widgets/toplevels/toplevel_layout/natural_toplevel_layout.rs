@@ -0,0 +1,260 @@
+use cosmic::iced::{Length, Point, Rectangle, Size, Vector};
+
+use super::{LayoutToplevel, ToplevelLayout};
+use crate::view::TOPLEVEL_TITLE_HEIGHT;
+
+/// Limit on passes moving overlapping previews apart. Any overlap remaining after
+/// this is left as is.
+const MAX_ITERATIONS: usize = 100;
+
+/// Passes estimating the scale, so the title above each preview fits
+const TITLE_PASSES: usize = 3;
+
+/// Places previews where their toplevels are on the output, like the "natural"
+/// window overview layouts of GNOME and KWin.
+///
+/// Overlapping toplevels are moved apart, each in the direction it's already
+/// offset from the other, so relative positions are preserved. The result is then
+/// scaled down to fit.
+pub(crate) struct NaturalToplevelLayout {
+    spacing: u32,
+}
+
+impl NaturalToplevelLayout {
+    pub fn new(spacing: u32) -> Self {
+        Self { spacing }
+    }
+}
+
+fn separate_overlapping(rects: &mut [Rectangle], max_limit: Size) {
+    for _ in 0..MAX_ITERATIONS {
+        let mut overlapped = false;
+        for i in 0..rects.len() {
+            for j in i + 1..rects.len() {
+                let (a, b) = (rects[i], rects[j]);
+                let overlap_x = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+                let overlap_y = (a.y + a.height).min(b.y + b.height) - a.y.max(b.y);
+                if overlap_x <= 0. || overlap_y <= 0. {
+                    continue;
+                }
+                overlapped = true;
+
+                // Move along whichever axis needs less movement, relative to the space
+                // available on that axis
+                let diff = b.center() - a.center();
+                if overlap_x / max_limit.width <= overlap_y / max_limit.height {
+                    let offset = overlap_x.copysign(diff.x) / 2.;
+                    rects[i].x -= offset;
+                    rects[j].x += offset;
+                } else {
+                    let offset = overlap_y.copysign(diff.y) / 2.;
+                    rects[i].y -= offset;
+                    rects[j].y += offset;
+                }
+            }
+        }
+        if !overlapped {
+            break;
+        }
+    }
+}
+
+impl NaturalToplevelLayout {
+    /// Rectangles on the output, with previews of unknown geometry centered at
+    /// their preferred aspect ratio, and room for a title above each of
+    /// `title_height` in output coordinates. Overlaps are then separated.
+    fn output_rects(
+        &self,
+        max_limit: Size,
+        toplevels: &[LayoutToplevel<'_>],
+        title_height: f32,
+    ) -> Vec<Rectangle> {
+        let mut rects = toplevels
+            .iter()
+            .map(|t| {
+                let geometry = t.geometry.unwrap_or_else(|| {
+                    let image = Size::new(
+                        t.preferred_size.width,
+                        (t.preferred_size.height - TOPLEVEL_TITLE_HEIGHT).max(0.),
+                    );
+                    let half = Size::new(max_limit.width / 2., max_limit.height / 2.);
+                    let scale = if image.width > 0. && image.height > 0. {
+                        (half.width / image.width).min(half.height / image.height)
+                    } else {
+                        1.
+                    };
+                    let size = image * scale;
+                    Rectangle::new(
+                        Point::new(
+                            (max_limit.width - size.width) / 2.,
+                            (max_limit.height - size.height) / 2.,
+                        ),
+                        size,
+                    )
+                });
+                Rectangle::new(
+                    geometry.position() - Vector::new(0., title_height),
+                    Size::new(geometry.width, geometry.height + title_height),
+                )
+            })
+            .collect::<Vec<_>>();
+        separate_overlapping(&mut rects, max_limit);
+        rects
+    }
+}
+
+/// Scale to fit `bounds` within `max_limit`, without scaling up
+fn fit_scale(bounds: Rectangle, max_limit: Size) -> f32 {
+    if bounds.width <= 0. || bounds.height <= 0. {
+        return 1.;
+    }
+    (max_limit.width / bounds.width)
+        .min(max_limit.height / bounds.height)
+        .min(1.)
+}
+
+impl ToplevelLayout for NaturalToplevelLayout {
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fill, Length::Fill)
+    }
+
+    fn layout(
+        &self,
+        max_limit: Size,
+        toplevels: &[LayoutToplevel<'_>],
+    ) -> impl Iterator<Item = Rectangle> {
+        if max_limit.width <= 0. || max_limit.height <= 0. || toplevels.is_empty() {
+            return vec![Rectangle::default(); toplevels.len()].into_iter();
+        }
+
+        // The title doesn't scale with the preview, so the space it needs in output
+        // coordinates depends on the scale. Start without it and refine.
+        let mut scale = 1.;
+        let mut rects = Vec::new();
+        let mut bounds = Rectangle::default();
+        for _ in 0..TITLE_PASSES {
+            rects = self.output_rects(max_limit, toplevels, TOPLEVEL_TITLE_HEIGHT / scale);
+            bounds = rects
+                .iter()
+                .copied()
+                .reduce(|a, b| a.union(&b))
+                .unwrap_or_default();
+            let new_scale = fit_scale(bounds, max_limit);
+            if new_scale >= scale {
+                break;
+            }
+            scale = new_scale;
+        }
+        // Center within available space
+        let offset = Vector::new(
+            (max_limit.width - bounds.width * scale) / 2.,
+            (max_limit.height - bounds.height * scale) / 2.,
+        );
+
+        let spacing = self.spacing as f32;
+        rects
+            .into_iter()
+            .map(|rect| {
+                let position =
+                    Point::new((rect.x - bounds.x) * scale, (rect.y - bounds.y) * scale) + offset;
+                let size = rect.size() * scale;
+                // Leave space between previews that are touching
+                let inset = (spacing / 2.).min(size.width / 4.).min(size.height / 4.);
+                Rectangle::new(
+                    position + Vector::new(inset, inset),
+                    Size::new(size.width - 2. * inset, size.height - 2. * inset),
+                )
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use super::*;
+
+    fn toplevel(geometry: Option<Rectangle>) -> LayoutToplevel<'static> {
+        LayoutToplevel {
+            preferred_size: Size::new(800., 600. + TOPLEVEL_TITLE_HEIGHT),
+            geometry,
+            _phantom_data: PhantomData,
+        }
+    }
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rectangle {
+        Rectangle::new(Point::new(x, y), Size::new(width, height))
+    }
+
+    /// Overlap by more than rounding error, since separating several previews
+    /// only converges towards no overlap
+    fn overlaps(a: &Rectangle, b: &Rectangle) -> bool {
+        let overlap_x = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+        let overlap_y = (a.y + a.height).min(b.y + b.height) - a.y.max(b.y);
+        overlap_x > 0.5 && overlap_y > 0.5
+    }
+
+    #[test]
+    fn overlapping_moved_apart_keeping_order() {
+        let mut rects = vec![rect(100., 100., 400., 300.), rect(300., 150., 400., 300.)];
+        separate_overlapping(&mut rects, Size::new(1920., 1080.));
+        assert!(!overlaps(&rects[0], &rects[1]));
+        // Moved horizontally, since that needs less movement
+        assert_eq!(rects[0].y, 100.);
+        assert_eq!(rects[1].y, 150.);
+        assert!(rects[0].x < rects[1].x);
+    }
+
+    #[test]
+    fn identical_rects_separated() {
+        let mut rects = vec![rect(0., 0., 400., 300.); 3];
+        separate_overlapping(&mut rects, Size::new(1920., 1080.));
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                assert!(!overlaps(a, b), "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn previews_fit_with_title() {
+        let max_limit = Size::new(1920., 1080.);
+        let toplevels = [
+            toplevel(Some(rect(0., 0., 1920., 1080.))),
+            toplevel(Some(rect(0., 0., 1920., 1080.))),
+            toplevel(None),
+        ];
+        let rects = NaturalToplevelLayout::new(8)
+            .layout(max_limit, &toplevels)
+            .collect::<Vec<_>>();
+        assert_eq!(rects.len(), 3);
+        for (i, a) in rects.iter().enumerate() {
+            assert!(a.x >= 0. && a.y >= 0., "{a:?}");
+            assert!(a.x + a.width <= max_limit.width + 0.01, "{a:?}");
+            assert!(a.y + a.height <= max_limit.height + 0.01, "{a:?}");
+            assert!(a.height > TOPLEVEL_TITLE_HEIGHT, "{a:?}");
+            for b in &rects[i + 1..] {
+                assert!(!overlaps(a, b), "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn empty_sizes_are_finite() {
+        let layout = NaturalToplevelLayout::new(8);
+        let toplevels = [toplevel(Some(rect(0., 0., 0., 0.))), toplevel(None)];
+        for max_limit in [Size::ZERO, Size::new(0., 1080.), Size::new(1920., 1080.)] {
+            for rect in layout.layout(max_limit, &toplevels) {
+                assert!(
+                    rect.x.is_finite()
+                        && rect.y.is_finite()
+                        && rect.width.is_finite()
+                        && rect.height.is_finite(),
+                    "{rect:?} in {max_limit:?}"
+                );
+            }
+        }
+    }
+}
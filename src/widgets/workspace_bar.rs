@@ -7,9 +7,11 @@ use cosmic::iced::advanced::widget::{Operation, Tree};
 use cosmic::iced::advanced::{Clipboard, Layout, Shell, Widget, mouse, renderer};
 use cosmic::iced::core::clipboard::DndDestinationRectangles;
 use cosmic::iced::event::Event;
-use cosmic::iced::{Length, Point, Rectangle, Size};
+use cosmic::iced::{Length, Point, Rectangle, Size, keyboard};
 use std::marker::PhantomData;
 
+use super::keyboard_nav::{self, Direction, Navigation};
+
 // Duplicate of private methods
 trait AxisExt {
     fn main(&self, size: Size) -> f32;
@@ -40,10 +42,15 @@ impl AxisExt for Axis {
     }
 }
 
-pub fn workspace_bar<Msg>(children: Vec<cosmic::Element<Msg>>, axis: Axis) -> WorkspaceBar<Msg> {
+pub fn workspace_bar<'a, Msg>(
+    children: Vec<cosmic::Element<'a, Msg>>,
+    axis: Axis,
+) -> WorkspaceBar<'a, Msg> {
     WorkspaceBar {
         axis,
         children,
+        focused: None,
+        on_navigate: None,
         _msg: PhantomData,
     }
 }
@@ -51,9 +58,25 @@ pub fn workspace_bar<Msg>(children: Vec<cosmic::Element<Msg>>, axis: Axis) -> Wo
 pub struct WorkspaceBar<'a, Msg> {
     axis: Axis,
     children: Vec<cosmic::Element<'a, Msg>>,
+    focused: Option<usize>,
+    on_navigate: Option<Box<dyn Fn(Navigation) -> Msg + 'a>>,
     _msg: PhantomData<Msg>,
 }
 
+impl<'a, Msg> WorkspaceBar<'a, Msg> {
+    /// Index of child that has keyboard focus, and should have a focus ring drawn
+    pub fn focused(mut self, focused: Option<usize>) -> Self {
+        self.focused = focused;
+        self
+    }
+
+    /// Called when an arrow key is pressed while a child is focused
+    pub fn on_navigate(mut self, on_navigate: impl Fn(Navigation) -> Msg + 'a) -> Self {
+        self.on_navigate = Some(Box::new(on_navigate));
+        self
+    }
+}
+
 impl<Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for WorkspaceBar<'_, Msg> {
     fn size(&self) -> Size<Length> {
         Size {
@@ -144,6 +167,21 @@ impl<Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for WorkspaceBar<'_, Msg>
         shell: &mut Shell<'_, Msg>,
         viewport: &Rectangle,
     ) {
+        if let Some(focused) = self.focused
+            && let Some(on_navigate) = &self.on_navigate
+            && let Event::Keyboard(keyboard::Event::KeyPressed { key, .. }) = event
+            && let Some(direction) = Direction::from_key(key)
+        {
+            let rects = layout.children().map(|l| l.bounds()).collect::<Vec<_>>();
+            let navigation = match keyboard_nav::spatial_neighbor(&rects, focused, direction) {
+                Some(idx) => Navigation::Focus(idx),
+                None => Navigation::Exit(direction),
+            };
+            shell.publish(on_navigate(navigation));
+            shell.capture_event();
+            return;
+        }
+
         for ((child, state), layout) in self
             .children
             .iter_mut()
@@ -198,6 +236,9 @@ impl<Msg> Widget<Msg, cosmic::Theme, cosmic::Renderer> for WorkspaceBar<'_, Msg>
                     .as_widget()
                     .draw(state, renderer, theme, style, layout, cursor, &viewport);
             }
+            if let Some(layout) = self.focused.and_then(|i| layout.children().nth(i)) {
+                keyboard_nav::draw_focus_ring(renderer, theme, layout.bounds());
+            }
         }
     }
 